@@ -1,18 +1,67 @@
-use crate::comment::{Comment, ContextLine};
-use crate::diff::{DiffLine, LineType};
+use crate::comment::{
+    self, Category, Comment, CommentFilter, CommentStatus, ContextLine, Severity, Suggestion,
+};
+use crate::config::Hooks;
+use crate::diff::{self, DiffLine, FileSpan, LineType};
+use crate::editor::CommentEditor;
+use crate::export::{self, Format, ReviewInfo};
+use crate::hooks::{self, HookEnv, HookEvent, HookResult};
+use crate::session::{ReviewedRevisions, ViewedState};
+use crate::suggestion;
+use crate::vim::KeyParser;
+use crate::watch::DiffWatcher;
+use chrono::{DateTime, Local};
+use std::collections::BTreeSet;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Mode {
     Normal,
-    Insert { comment_text: String },
-    Search { query: String },
-    Command { command: String },
+    Insert {
+        editor: CommentEditor,
+        target: InsertTarget,
+    },
+    Search {
+        query: String,
+    },
+    Command {
+        command: String,
+    },
     Help,
+    HookOutput, // Full output of the last hook, opened with :hook
+}
+
+/// What the text typed in insert mode becomes once saved
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertTarget {
+    New,
+    Suggest(usize), // Suggested change replacing this many lines from the cursor
+    Edit(usize),    // Index of the comment being edited
+    Reply(Uuid),    // Id of the thread root being answered
 }
 
 pub struct App {
     pub diff_lines: Vec<DiffLine>,
+    pub files: Vec<FileSpan>,
     pub comments: Vec<Comment>,
+    pub loaded_comments: Vec<Comment>, // This review's comments as read from the session
+    pub viewed: ViewedState,
+    closed_folds: BTreeSet<usize>, // Start lines of closed file/hunk folds
+    visible: Vec<usize>,           // Lines the folds leave shown, kept by update_folds
+    pub keys: KeyParser,
+    pub register: String,             // Text yanked with yy
+    pub message: Option<String>,      // Feedback shown in the status bar
+    pub compose_requested: bool,      // Open $EDITOR for the cursor line's comment
+    pub author: Option<String>,       // Signed on new comments and replies
+    pub export_filter: CommentFilter, // Which comments are written to the output file
+    pub export_format: Format,        // Format of the output file
+    pub review: ReviewInfo,           // Ref and commits under review, for exports
+    pub hooks: Hooks,
+    pub hook_result: Option<HookResult>, // Last finished hook, shown with :hook
+    running_hook: Option<Receiver<HookResult>>,
+    pub watcher: Option<DiffWatcher>,         // Set with --watch
+    pub reloaded_at: Option<DateTime<Local>>, // Last time the diff was reloaded
     pub cursor: usize,
     pub scroll_offset: usize,
     pub mode: Mode,
@@ -23,10 +72,49 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(diff_lines: Vec<DiffLine>, comments: Vec<Comment>, output_file: String) -> Self {
-        Self {
+    pub fn new(
+        diff_lines: Vec<DiffLine>,
+        comments: Vec<Comment>,
+        mut viewed: ViewedState,
+        output_file: String,
+    ) -> Self {
+        let files = diff::index_files(&diff_lines);
+        viewed.retain_current(&files);
+
+        // Viewed files and hunks start out folded
+        let mut closed_folds = BTreeSet::new();
+        for file in &files {
+            if viewed.is_file_viewed(file) {
+                closed_folds.insert(file.start);
+            }
+            for hunk in &file.hunks {
+                if viewed.is_hunk_viewed(file, hunk.hash) {
+                    closed_folds.insert(hunk.start);
+                }
+            }
+        }
+
+        let mut app = Self {
             diff_lines,
+            files,
             comments,
+            loaded_comments: Vec::new(),
+            viewed,
+            closed_folds,
+            visible: Vec::new(),
+            keys: KeyParser::default(),
+            register: String::new(),
+            message: None,
+            compose_requested: false,
+            author: None,
+            export_filter: CommentFilter::default(),
+            export_format: Format::default(),
+            review: ReviewInfo::default(),
+            hooks: Hooks::default(),
+            hook_result: None,
+            running_hook: None,
+            watcher: None,
+            reloaded_at: None,
             cursor: 0,
             scroll_offset: 0,
             mode: Mode::Normal,
@@ -34,18 +122,20 @@ impl App {
             search_matches: Vec::new(),
             current_match: 0,
             output_file,
-        }
+        };
+        app.update_folds();
+        app
     }
 
     pub fn move_cursor_down(&mut self) {
-        if self.cursor < self.diff_lines.len().saturating_sub(1) {
-            self.cursor += 1;
+        if let Some(next) = (self.cursor + 1..self.diff_lines.len()).find(|&i| !self.is_hidden(i)) {
+            self.cursor = next;
         }
     }
 
     pub fn move_cursor_up(&mut self) {
-        if self.cursor > 0 {
-            self.cursor -= 1;
+        if let Some(prev) = (0..self.cursor).rev().find(|&i| !self.is_hidden(i)) {
+            self.cursor = prev;
         }
     }
 
@@ -56,20 +146,440 @@ impl App {
 
     pub fn move_to_bottom(&mut self) {
         self.cursor = self.diff_lines.len().saturating_sub(1);
+        self.reveal_cursor();
+    }
+
+    /// Jump to a 1-based diff line, as with "10G" or "10gg"
+    pub fn move_to_line(&mut self, line: usize) {
+        self.cursor = line
+            .saturating_sub(1)
+            .min(self.diff_lines.len().saturating_sub(1));
+        self.reveal_cursor();
     }
 
     pub fn page_down(&mut self, page_size: usize) {
-        self.cursor = (self.cursor + page_size).min(self.diff_lines.len().saturating_sub(1));
+        for _ in 0..page_size {
+            self.move_cursor_down();
+        }
     }
 
     pub fn page_up(&mut self, page_size: usize) {
-        self.cursor = self.cursor.saturating_sub(page_size);
+        for _ in 0..page_size {
+            self.move_cursor_up();
+        }
+    }
+
+    /// Move to the first line after the cursor matching the predicate
+    ///
+    /// Lines hidden in closed folds are skipped unless `open_folds` is set,
+    /// in which case the folds around the target are opened.
+    fn jump_forward(&mut self, open_folds: bool, is_target: impl Fn(&Self, usize) -> bool) {
+        if let Some(next) = (self.cursor + 1..self.diff_lines.len())
+            .find(|&i| (open_folds || !self.is_hidden(i)) && is_target(self, i))
+        {
+            self.jump_to(next);
+        }
+    }
+
+    /// Move to the last line before the cursor matching the predicate
+    fn jump_backward(&mut self, open_folds: bool, is_target: impl Fn(&Self, usize) -> bool) {
+        if let Some(prev) = (0..self.cursor)
+            .rev()
+            .find(|&i| (open_folds || !self.is_hidden(i)) && is_target(self, i))
+        {
+            self.jump_to(prev);
+        }
+    }
+
+    /// ]c
+    pub fn next_hunk(&mut self) {
+        self.jump_forward(false, |app, i| {
+            app.diff_lines[i].line_type == LineType::HunkHeader
+        });
+    }
+
+    /// [c
+    pub fn prev_hunk(&mut self) {
+        self.jump_backward(false, |app, i| {
+            app.diff_lines[i].line_type == LineType::HunkHeader
+        });
+    }
+
+    /// ]f or }
+    pub fn next_file(&mut self) {
+        self.jump_forward(false, |app, i| app.files.iter().any(|f| f.start == i));
+    }
+
+    /// [f or {
+    pub fn prev_file(&mut self) {
+        self.jump_backward(false, |app, i| app.files.iter().any(|f| f.start == i));
+    }
+
+    /// ]m: comments are always reachable, even inside closed folds
+    pub fn next_comment(&mut self) {
+        self.jump_forward(true, |app, i| app.has_comment_at(i));
+    }
+
+    /// [m
+    pub fn prev_comment(&mut self) {
+        self.jump_backward(true, |app, i| app.has_comment_at(i));
+    }
+
+    /// ]x
+    pub fn next_unreviewed_file(&mut self) {
+        self.jump_forward(false, |app, i| {
+            app.files
+                .iter()
+                .any(|f| f.start == i && !app.is_file_reviewed(f))
+        });
+    }
+
+    /// Whether any comment is anchored to the given diff line
+    pub fn has_comment_at(&self, index: usize) -> bool {
+        self.comment_index_at(index).is_some()
+    }
+
+    /// Index of the first comment anchored to the given diff line
+    pub fn comment_index_at(&self, index: usize) -> Option<usize> {
+        let line = self.diff_lines.get(index)?;
+        self.comments.iter().position(|c| c.is_on(line))
+    }
+
+    /// Index of the file span containing the given line
+    pub fn file_index_at(&self, index: usize) -> Option<usize> {
+        self.files.iter().position(|f| f.contains(index))
+    }
+
+    /// Whether the line is hidden inside a closed fold
+    ///
+    /// The first line of a closed fold stays visible and is rendered as the
+    /// fold's summary line.
+    pub fn is_hidden(&self, index: usize) -> bool {
+        self.visible.binary_search(&index).is_err()
+    }
+
+    /// Start line of the closed fold summarised on this line, if any
+    pub fn closed_fold_at(&self, index: usize) -> Option<usize> {
+        if self.closed_folds.contains(&index) && !self.is_hidden(index) {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Indices of the diff lines currently shown on screen
+    pub fn visible_lines(&self) -> &[usize] {
+        &self.visible
+    }
+
+    /// Screen row of a shown line, counting from the top of the diff
+    pub fn visible_row(&self, index: usize) -> Option<usize> {
+        self.visible.binary_search(&index).ok()
+    }
+
+    /// Work out which lines the closed folds hide; run whenever the folds
+    /// or the diff change, so drawing a frame doesn't have to
+    fn update_folds(&mut self) {
+        let mut hidden = vec![false; self.diff_lines.len()];
+        for file in &self.files {
+            if self.closed_folds.contains(&file.start) {
+                hidden[file.start + 1..file.end].fill(true);
+                continue;
+            }
+            for hunk in &file.hunks {
+                if self.closed_folds.contains(&hunk.start) {
+                    hidden[hunk.start + 1..hunk.end].fill(true);
+                }
+            }
+        }
+        self.visible = (0..hidden.len()).filter(|&i| !hidden[i]).collect();
+    }
+
+    /// Move the cursor back to the nearest shown line if it was collapsed away
+    fn reveal_cursor(&mut self) {
+        if self.is_hidden(self.cursor) {
+            self.move_cursor_up();
+        }
+    }
+
+    /// Mark or unmark the file under the cursor as viewed
+    pub fn toggle_file_viewed(&mut self) {
+        let Some(file_idx) = self.file_index_at(self.cursor) else {
+            return;
+        };
+        let file = &self.files[file_idx];
+        let start = file.start;
+        self.viewed.toggle_file(file);
+        self.save_viewed();
+
+        // Viewed files collapse, unviewed ones expand again
+        if self.viewed.is_file_viewed(&self.files[file_idx]) {
+            self.close_fold(start);
+        } else {
+            self.closed_folds.remove(&start);
+            self.update_folds();
+        }
+    }
+
+    /// Mark or unmark the hunk under the cursor as viewed
+    pub fn toggle_hunk_viewed(&mut self) {
+        let Some(file_idx) = self.file_index_at(self.cursor) else {
+            return;
+        };
+        let file = &self.files[file_idx];
+        let Some(hunk) = file.hunk_at(self.cursor) else {
+            return;
+        };
+        let (start, hash) = (hunk.start, hunk.hash);
+        self.viewed.toggle_hunk(file, hash);
+        self.save_viewed();
+
+        if self.viewed.is_hunk_viewed(&self.files[file_idx], hash) {
+            self.close_fold(start);
+        } else {
+            self.closed_folds.remove(&start);
+            self.update_folds();
+        }
+    }
+
+    /// Whether the file was marked viewed, either as a whole or hunk by hunk
+    pub fn is_file_reviewed(&self, file: &FileSpan) -> bool {
+        self.viewed.is_file_viewed(file)
+            || (!file.hunks.is_empty()
+                && file
+                    .hunks
+                    .iter()
+                    .all(|h| self.viewed.is_hunk_viewed(file, h.hash)))
+    }
+
+    /// Number of files in the diff that have been reviewed
+    pub fn reviewed_count(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|f| self.is_file_reviewed(f))
+            .count()
+    }
+
+    fn save_viewed(&self) {
+        let _ = self.viewed.save();
+    }
+
+    /// Start lines of the folds enclosing the cursor: (file, hunk)
+    fn folds_at_cursor(&self) -> Option<(usize, Option<usize>)> {
+        let file = &self.files[self.file_index_at(self.cursor)?];
+        Some((file.start, file.hunk_at(self.cursor).map(|h| h.start)))
+    }
+
+    fn close_fold(&mut self, start: usize) {
+        self.closed_folds.insert(start);
+        self.update_folds();
+        if self.is_hidden(self.cursor) {
+            self.cursor = start;
+        }
+    }
+
+    /// za: open the closed fold under the cursor, or close the innermost one
+    pub fn toggle_fold(&mut self) {
+        let Some((file, hunk)) = self.folds_at_cursor() else {
+            return;
+        };
+
+        if self.closed_folds.contains(&file) {
+            self.closed_folds.remove(&file);
+        } else if let Some(hunk) = hunk {
+            if !self.closed_folds.remove(&hunk) {
+                self.close_fold(hunk);
+            }
+        } else {
+            self.close_fold(file);
+        }
+        self.update_folds();
+    }
+
+    /// zc: close the innermost open fold under the cursor
+    pub fn close_fold_at_cursor(&mut self) {
+        let Some((file, hunk)) = self.folds_at_cursor() else {
+            return;
+        };
+
+        match hunk {
+            Some(hunk) if !self.closed_folds.contains(&hunk) => self.close_fold(hunk),
+            _ => self.close_fold(file),
+        }
+    }
+
+    /// zo: open the closed fold under the cursor
+    pub fn open_fold_at_cursor(&mut self) {
+        let Some((file, hunk)) = self.folds_at_cursor() else {
+            return;
+        };
+
+        if !self.closed_folds.remove(&file) {
+            if let Some(hunk) = hunk {
+                self.closed_folds.remove(&hunk);
+            }
+        }
+        self.update_folds();
+    }
+
+    /// zM: fold every file
+    pub fn close_all_folds(&mut self) {
+        let starts: Vec<usize> = self.files.iter().map(|f| f.start).collect();
+        self.closed_folds.extend(starts);
+        self.update_folds();
+        if let Some((file, _)) = self.folds_at_cursor() {
+            self.cursor = file;
+        }
+    }
+
+    /// zR: open every fold
+    pub fn open_all_folds(&mut self) {
+        self.closed_folds.clear();
+        self.update_folds();
+    }
+
+    /// Open any folds hiding the given line, like vim does when jumping to a
+    /// search match
+    fn open_folds_around(&mut self, index: usize) {
+        if let Some(file) = self.file_index_at(index).map(|i| &self.files[i]) {
+            self.closed_folds.remove(&file.start);
+            if let Some(hunk) = file.hunk_at(index) {
+                self.closed_folds.remove(&hunk.start);
+            }
+        }
+        self.update_folds();
+    }
+
+    /// Jump to a line, opening folds if it is hidden inside one
+    fn jump_to(&mut self, index: usize) {
+        if self.is_hidden(index) {
+            self.open_folds_around(index);
+        }
+        self.cursor = index;
     }
 
     pub fn enter_insert_mode(&mut self) {
         self.mode = Mode::Insert {
-            comment_text: String::new(),
+            editor: CommentEditor::new(String::new()),
+            target: InsertTarget::New,
+        };
+    }
+
+    /// Open the comment on the cursor line for editing, or start a new one
+    /// if the line has none
+    pub fn edit_comment_at_cursor(&mut self) {
+        match self.comment_index_at(self.cursor) {
+            Some(idx) => {
+                self.mode = Mode::Insert {
+                    editor: CommentEditor::new(self.comments[idx].body()),
+                    target: InsertTarget::Edit(idx),
+                };
+            }
+            None => self.enter_insert_mode(),
+        }
+    }
+
+    /// gs: suggest a change to `count` lines from the cursor, starting from
+    /// their current text in a ```suggestion block
+    pub fn start_suggestion(&mut self, count: usize) {
+        if self.diff_lines[self.cursor].file_path == diff::COMMIT_MSG {
+            self.message = Some("Can't suggest a change to the commit message".to_string());
+            return;
+        }
+        let lines = self.suggestion_lines(self.cursor, count);
+        if lines.is_empty() {
+            self.message = Some("Can't suggest a change to a removed or header line".to_string());
+            return;
+        }
+
+        let mut editor = CommentEditor::new(format!("```suggestion\n{}\n```", lines.join("\n")));
+        // Land on the last code line so it can be edited right away
+        editor.move_up();
+        editor.move_line_end();
+        self.mode = Mode::Insert {
+            editor,
+            target: InsertTarget::Suggest(lines.len()),
+        };
+    }
+
+    /// Text of up to `count` consecutive new-file lines starting at `index`,
+    /// without their diff markers; stops at the end of the hunk
+    fn suggestion_lines(&self, index: usize, count: usize) -> Vec<String> {
+        if self
+            .diff_lines
+            .get(index)
+            .and_then(|l| l.new_line_no)
+            .is_none()
+        {
+            return Vec::new();
+        }
+        self.diff_lines[index..]
+            .iter()
+            .take_while(|l| {
+                matches!(
+                    l.line_type,
+                    LineType::Added | LineType::Context | LineType::Removed
+                )
+            })
+            .filter(|l| l.new_line_no.is_some())
+            .take(count.max(1))
+            .map(|l| l.content.get(1..).unwrap_or("").to_string())
+            .collect()
+    }
+
+    /// Ask the TUI to suspend and compose the cursor line's comment in $EDITOR
+    pub fn request_compose(&mut self) {
+        if self.diff_lines[self.cursor].new_line_no.is_some() {
+            self.compose_requested = true;
+        } else {
+            self.message = Some("Can't comment on a removed or header line".to_string());
+        }
+    }
+
+    /// Header for the $EDITOR temp file: where the comment goes and the diff
+    /// context leading up to it
+    pub fn compose_header(&self) -> Vec<String> {
+        let line = &self.diff_lines[self.cursor];
+        let mut header = vec![
+            format!(
+                "Review comment for {}:{}",
+                line.file_path,
+                line.new_line_no.unwrap_or(0)
+            ),
+            "These '#' lines are ignored. An empty comment aborts.".to_string(),
+            String::new(),
+        ];
+        header.extend(
+            self.extract_context_before(self.cursor, 5)
+                .into_iter()
+                .map(|ctx| ctx.content),
+        );
+        header
+    }
+
+    /// Text of the comment $EDITOR should start with
+    pub fn compose_body(&self) -> String {
+        self.comment_index_at(self.cursor)
+            .map(|idx| self.comments[idx].body())
+            .unwrap_or_default()
+    }
+
+    /// Save the text written in $EDITOR, updating the cursor line's comment
+    /// if it already has one
+    pub fn finish_compose(&mut self, text: String) {
+        if text.trim().is_empty() {
+            self.message = Some("Empty comment, nothing saved".to_string());
+            return;
+        }
+
+        self.mode = Mode::Insert {
+            editor: CommentEditor::new(text),
+            target: match self.comment_index_at(self.cursor) {
+                Some(idx) => InsertTarget::Edit(idx),
+                None => InsertTarget::New,
+            },
         };
+        self.exit_to_normal_mode();
     }
 
     pub fn enter_search_mode(&mut self) {
@@ -85,6 +595,23 @@ impl App {
     }
 
     pub fn execute_command(&mut self, command: &str) {
+        // Commands that take an argument, e.g. ":severity blocker"
+        if let Some((name, arg)) = command.trim().split_once(' ') {
+            match name {
+                "severity" => match Severity::parse(arg) {
+                    Some(severity) => self.set_thread_labels(Some(severity), None),
+                    None => self.message = Some(format!("Unknown severity: {}", arg.trim())),
+                },
+                "category" => match Category::parse(arg) {
+                    Some(category) => self.set_thread_labels(None, Some(category)),
+                    None => self.message = Some(format!("Unknown category: {}", arg.trim())),
+                },
+                "accept" | "dismiss" if arg.trim() == "all" => self.triage_all(name == "accept"),
+                _ => {}
+            }
+            return;
+        }
+
         match command.trim() {
             "wq" | "x" => {
                 self.save_all_comments();
@@ -100,75 +627,589 @@ impl App {
             "w" => {
                 self.save_all_comments();
             }
+            "edit" | "e" => self.request_compose(),
+            "reply" => self.start_reply(),
+            "resolve" => self.set_thread_status(CommentStatus::Resolved),
+            "wontfix" => self.set_thread_status(CommentStatus::WontFix),
+            "reopen" => self.set_thread_status(CommentStatus::Open),
+            "apply" => self.apply_suggestions(),
+            "hook" => match self.hook_result {
+                Some(_) => self.mode = Mode::HookOutput,
+                None => self.message = Some("No hook has run yet".to_string()),
+            },
+            "accept" => self.accept_pending(),
+            "dismiss" => self.dismiss_pending(),
+            "severity" => {
+                self.message = Some(format!(
+                    "Severity needs a level: {}",
+                    Severity::ALL.map(|s| s.label()).join(", ")
+                ))
+            }
+            "category" => {
+                self.message = Some(format!(
+                    "Category needs a name: {}",
+                    Category::ALL.map(|c| c.label()).join(", ")
+                ))
+            }
             _ => {}
         }
     }
 
-    pub fn save_all_comments(&self) {
-        let _ = crate::comment::save_comments_to_file(&self.comments, &self.output_file);
+    /// :apply: write open suggested changes into the working tree
+    pub fn apply_suggestions(&mut self) {
+        let report = crate::vcs::repo_root()
+            .map(|root| suggestion::apply_suggestions(&mut self.comments, &root));
+        self.message = Some(match report {
+            Ok(report) => match self.save_session() {
+                Ok(()) => report.summary(),
+                Err(e) => format!("{}; saving the session failed: {:#}", report.summary(), e),
+            },
+            Err(e) => format!("Apply failed: {}", e),
+        });
+    }
+
+    pub fn save_all_comments(&mut self) {
+        // Keep the session under .vim-review/ alongside the viewed state;
+        // merging it first picks up comments vr serve changed
+        let saved = self.save_session();
+        let exported = self.export_filter.apply(&self.comments);
+        let _ = export::write(
+            &self.export_format,
+            &exported,
+            &self.review,
+            &self.diff_lines,
+            &self.output_file,
+        );
+        if let Err(e) = saved {
+            self.message = Some(format!("Saving the session failed: {:#}", e));
+            return;
+        }
+        // Uncommitted changes have no commit to compare a later version with
+        if self.review.head_sha.is_some() {
+            let _ = ReviewedRevisions {
+                base_sha: self.review.base_sha.clone(),
+                head_sha: self.review.head_sha.clone(),
+            }
+            .save();
+        }
+
+        if let Some(command) = self.hooks.on_save.clone() {
+            if self.running_hook.is_some() {
+                self.message = Some("on_save hook is still running".to_string());
+            } else {
+                self.running_hook = Some(hooks::spawn(
+                    HookEvent::Save,
+                    command,
+                    self.hook_env(),
+                    self.hooks.stdin,
+                ));
+                self.message = Some("Running on_save hook...".to_string());
+            }
+        }
+    }
+
+    /// Write this review's comments to .vim-review/comments.json, merged
+    /// with the comments of other reviews and changes made by vr serve
+    fn save_session(&mut self) -> anyhow::Result<()> {
+        // Replies and resolves written by vr serve meanwhile show up here too
+        self.comments = comment::save_review(&self.comments, &self.loaded_comments)?;
+        self.loaded_comments = self.comments.clone();
+        Ok(())
+    }
+
+    /// What hooks are told about the review
+    pub fn hook_env(&self) -> HookEnv {
+        HookEnv {
+            output_file: self.output_file.clone(),
+            format: self.export_format.clone(),
+            comment_count: self.export_filter.apply(&self.comments).len(),
+        }
+    }
+
+    /// Pick up the result of a background hook once it has finished
+    pub fn poll_hook(&mut self) {
+        let Some(rx) = &self.running_hook else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(result) => self.finish_hook(result),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.running_hook = None,
+        }
+    }
+
+    /// Wait for a background hook still running when vr exits
+    pub fn wait_for_hook(&mut self) -> Option<&HookResult> {
+        let result = self.running_hook.take()?.recv().ok()?;
+        self.hook_result = Some(result);
+        self.hook_result.as_ref()
+    }
+
+    fn finish_hook(&mut self, result: HookResult) {
+        self.running_hook = None;
+        let hint = if result.output.trim().is_empty() {
+            ""
+        } else {
+            " (:hook for output)"
+        };
+        self.message = Some(format!("{}{}", result.summary(), hint));
+        self.hook_result = Some(result);
+    }
+
+    /// Swap in a new diff from the watcher, unless a comment is being typed
+    pub fn poll_watch(&mut self) {
+        if matches!(self.mode, Mode::Insert { .. }) {
+            return;
+        }
+        let Some(update) = self.watcher.as_ref().and_then(|w| w.poll()) else {
+            return;
+        };
+
+        match update.and_then(|text| diff::parse_diff(&text)) {
+            // Nothing to show instead; committing everything ends up here
+            Ok(diff_lines) if diff_lines.is_empty() => {
+                self.message = Some("No changes left to review; showing the last diff".to_string());
+            }
+            Ok(diff_lines) => {
+                let moved = self.reload_diff(diff_lines);
+                self.message = Some(match moved {
+                    0 => "Diff reloaded".to_string(),
+                    n => format!(
+                        "Diff reloaded, {} comment{} moved",
+                        n,
+                        if n == 1 { "" } else { "s" }
+                    ),
+                });
+            }
+            Err(e) => self.message = Some(format!("Reload failed: {:#}", e)),
+        }
+    }
+
+    /// Replace the diff, keeping the cursor on the same logical line and
+    /// moving comments along with their code; returns how many comments
+    /// changed line
+    pub fn reload_diff(&mut self, diff_lines: Vec<DiffLine>) -> usize {
+        // Keep the cursor on the same screen row
+        let row = self.visible_row(self.cursor);
+        let screen_row = row.unwrap_or(0).saturating_sub(self.scroll_offset);
+
+        let old = std::mem::replace(&mut self.diff_lines, diff_lines);
+        let old_files = std::mem::replace(&mut self.files, diff::index_files(&self.diff_lines));
+        self.viewed.retain_current(&self.files);
+
+        // Folds closed by hand stay closed if their file or hunk is still
+        // there; viewed ones are closed as at startup
+        let closed: Vec<(String, Option<u64>)> = old_files
+            .iter()
+            .flat_map(|f| {
+                let file = self
+                    .closed_folds
+                    .contains(&f.start)
+                    .then(|| (f.path.clone(), None));
+                let hunks = f
+                    .hunks
+                    .iter()
+                    .filter(|h| self.closed_folds.contains(&h.start))
+                    .map(|h| (f.path.clone(), Some(h.hash)));
+                file.into_iter().chain(hunks).collect::<Vec<_>>()
+            })
+            .collect();
+        self.closed_folds.clear();
+        for file in &self.files {
+            if self.viewed.is_file_viewed(file) || closed.contains(&(file.path.clone(), None)) {
+                self.closed_folds.insert(file.start);
+            }
+            for hunk in &file.hunks {
+                if self.viewed.is_hunk_viewed(file, hunk.hash)
+                    || closed.contains(&(file.path.clone(), Some(hunk.hash)))
+                {
+                    self.closed_folds.insert(hunk.start);
+                }
+            }
+        }
+        self.update_folds();
+
+        let expected: Vec<usize> = self
+            .comments
+            .iter()
+            .map(|c| diff::map_line(&old, &self.diff_lines, &c.file_path, c.line_number))
+            .collect();
+        let moved = comment::reanchor(&mut self.comments, &self.diff_lines, &expected);
+
+        self.cursor = self.map_index(&old, self.cursor);
+        self.reveal_cursor();
+        let row = self.visible_row(self.cursor);
+        self.scroll_offset = row.unwrap_or(0).saturating_sub(screen_row);
+
+        self.search_matches.clear();
+        self.reloaded_at = Some(Local::now());
+        moved
+    }
+
+    /// Index in the current diff of the line at `index` in `old`
+    fn map_index(&self, old: &[DiffLine], index: usize) -> usize {
+        let last = self.diff_lines.len().saturating_sub(1);
+        let Some(line) = old.get(index) else {
+            return last;
+        };
+        let in_file = |l: &DiffLine| l.file_path == line.file_path;
+
+        // A hunk header moves with the first line of its hunk
+        let new_line_no = line.new_line_no.or_else(|| match line.line_type {
+            LineType::HunkHeader => old[index..].iter().find_map(|l| l.new_line_no),
+            _ => None,
+        });
+        let found = match (new_line_no, line.old_line_no) {
+            (Some(line_no), _) => {
+                let target = diff::map_line(old, &self.diff_lines, &line.file_path, line_no);
+                self.diff_lines
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| in_file(l) && l.new_line_no.is_some())
+                    .min_by_key(|(_, l)| l.new_line_no.unwrap_or(0).abs_diff(target))
+                    .map(|(i, _)| i)
+            }
+            // Removed lines are the same in both diffs, until they're restored
+            (None, Some(line_no)) => self
+                .diff_lines
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| in_file(l) && l.old_line_no.is_some())
+                .min_by_key(|(_, l)| l.old_line_no.unwrap_or(0).abs_diff(line_no))
+                .map(|(i, _)| i),
+            (None, None) => None,
+        };
+
+        found
+            .or_else(|| {
+                self.files
+                    .iter()
+                    .find(|f| f.path == line.file_path)
+                    .map(|f| f.start)
+            })
+            .unwrap_or(index.min(last))
     }
 
     pub fn exit_to_normal_mode(&mut self) {
         // If we were in insert mode, save the comment
-        if let Mode::Insert { comment_text } = &self.mode {
-            if !comment_text.trim().is_empty() {
-                self.save_comment(comment_text.clone());
+        if let Mode::Insert { editor, target } = std::mem::replace(&mut self.mode, Mode::Normal) {
+            let comment_text = editor.into_text();
+            let is_empty = comment_text.trim().is_empty();
+            match target {
+                // Clearing an edited comment's text deletes it
+                InsertTarget::Edit(idx) if is_empty => self.remove_comment(idx),
+                InsertTarget::Edit(idx) => {
+                    // Editing an imported finding accepts it
+                    self.comments[idx].pending = false;
+                    self.comments[idx].edit(comment_text);
+                }
+                InsertTarget::Reply(root) if !is_empty => {
+                    if let Some(root) = self.comments.iter().find(|c| c.id == root) {
+                        let mut reply = root.reply(comment_text);
+                        reply.author = self.author.clone();
+                        self.comments.push(reply);
+                    }
+                }
+                InsertTarget::New if !is_empty => self.save_comment(comment_text, 1),
+                InsertTarget::Suggest(lines) if !is_empty => self.save_comment(comment_text, lines),
+                _ => {}
             }
         }
-
-        self.mode = Mode::Normal;
     }
 
-    pub fn save_comment(&mut self, text: String) {
+    /// Save a new comment on the cursor line; a ```suggestion block in the
+    /// text becomes a suggested change to `span` lines from the cursor
+    pub fn save_comment(&mut self, text: String, span: usize) {
         if let Some(line) = self.diff_lines.get(self.cursor) {
             // Only allow comments on added or context lines (not removed lines)
             if let Some(line_number) = line.new_line_no {
                 // Extract context: 5 lines before
                 let context = self.extract_context_before(self.cursor, 5);
-                let comment = Comment::new(line.file_path.clone(), line_number, text, context);
+                let (text, replacement) = comment::split_suggestion(&text);
+                let mut comment = Comment::new(line.file_path.clone(), line_number, text, context);
+                comment.commit = line.commit.clone();
+                comment.author = self.author.clone();
+                comment.apply_label_tags();
+                if let Some(replacement) = replacement {
+                    let original = self.suggestion_lines(self.cursor, span);
+                    comment.suggestion = Some(Suggestion {
+                        end_line: line_number + original.len().saturating_sub(1),
+                        original,
+                        replacement,
+                    });
+                }
                 self.comments.push(comment);
             }
         }
     }
 
-    /// Extract context lines before the given index (not after)
+    /// Extract context lines before the given index (not after), stopping
+    /// at the hunk header so the lines are contiguous in the file
     fn extract_context_before(&self, index: usize, context_size: usize) -> Vec<ContextLine> {
         let mut context = Vec::new();
 
         // Get context lines before (including the current line)
-        let start = index.saturating_sub(context_size);
-        let end = index + 1; // Include current line
-
-        for i in start..end {
-            if let Some(diff_line) = self.diff_lines.get(i) {
-                // Skip file headers and hunk headers
-                if matches!(
-                    diff_line.line_type,
-                    LineType::FileHeader | LineType::HunkHeader
-                ) {
-                    continue;
+        let end = (index + 1).min(self.diff_lines.len());
+        for diff_line in self.diff_lines[..end].iter().rev().take(context_size + 1) {
+            if matches!(
+                diff_line.line_type,
+                LineType::FileHeader | LineType::HunkHeader
+            ) {
+                break;
+            }
+
+            // The content already includes the prefix (+, -, or space)
+            // so we just use it directly
+            context.push(ContextLine {
+                content: diff_line.content.clone(),
+                prefix: String::new(), // Not used since content already has prefix
+                old_line_no: diff_line.old_line_no,
+                new_line_no: diff_line.new_line_no,
+            });
+        }
+
+        context.reverse();
+        context
+    }
+
+    /// Remove a comment, along with its replies if it starts a thread
+    fn remove_comment(&mut self, idx: usize) {
+        let removed = self.comments.remove(idx);
+        self.comments.retain(|c| c.in_reply_to != Some(removed.id));
+    }
+
+    /// Index of the first thread root anchored to the given diff line
+    pub fn thread_root_at(&self, index: usize) -> Option<usize> {
+        let line = self.diff_lines.get(index)?;
+        self.comments
+            .iter()
+            .position(|c| c.is_on(line) && comment::is_thread_root(c, &self.comments))
+    }
+
+    /// r: reply to the thread on the cursor line
+    pub fn start_reply(&mut self) {
+        match self.thread_root_at(self.cursor) {
+            Some(idx) => {
+                self.mode = Mode::Insert {
+                    editor: CommentEditor::new(String::new()),
+                    target: InsertTarget::Reply(self.comments[idx].id),
+                };
+            }
+            None => self.message = Some("No comment to reply to".to_string()),
+        }
+    }
+
+    /// Add imported findings as pending comments anchored on the diff
+    ///
+    /// Findings on lines the diff doesn't show, and ones already in the
+    /// session, are skipped. Only thread roots are marked pending; replies
+    /// are accepted or dismissed with their thread. Returns how many were
+    /// added and skipped.
+    pub fn import_comments(&mut self, comments: Vec<Comment>) -> (usize, usize) {
+        let (mut added, mut skipped) = (0, 0);
+        let first_new = self.comments.len();
+        for mut c in comments {
+            let anchor = self.diff_lines.iter().position(|l| c.is_on(l));
+            let duplicate = self.comments.iter().any(|e| {
+                e.id == c.id
+                    || (e.file_path == c.file_path
+                        && e.line_number == c.line_number
+                        && e.text == c.text)
+            });
+            let Some(index) = anchor.filter(|_| !duplicate) else {
+                skipped += 1;
+                continue;
+            };
+
+            c.context = self.extract_context_before(index, 5);
+            if let Some(s) = &mut c.suggestion {
+                if s.original.is_empty() {
+                    let count = s.end_line.saturating_sub(c.line_number) + 1;
+                    s.original = self.suggestion_lines(index, count);
+                    s.end_line = c.line_number + s.original.len().saturating_sub(1);
                 }
+            }
+            self.comments.push(c);
+            added += 1;
+        }
 
-                // The content already includes the prefix (+, -, or space)
-                // so we just use it directly
-                context.push(ContextLine {
-                    content: diff_line.content.clone(),
-                    prefix: String::new(), // Not used since content already has prefix
-                });
+        for idx in first_new..self.comments.len() {
+            self.comments[idx].pending =
+                comment::is_thread_root(&self.comments[idx], &self.comments);
+        }
+        (added, skipped)
+    }
+
+    /// Index of the first pending imported thread on the given diff line
+    fn pending_at(&self, index: usize) -> Option<usize> {
+        let line = self.diff_lines.get(index)?;
+        self.comments
+            .iter()
+            .position(|c| c.pending && c.is_on(line) && comment::is_thread_root(c, &self.comments))
+    }
+
+    /// A: accept the imported finding on the cursor line, keeping it as a
+    /// regular comment
+    pub fn accept_pending(&mut self) {
+        match self.pending_at(self.cursor) {
+            Some(idx) => {
+                let root = self.comments[idx].id;
+                for c in &mut self.comments {
+                    if c.id == root || c.in_reply_to == Some(root) {
+                        c.pending = false;
+                    }
+                }
+                self.message = Some("Finding accepted".to_string());
             }
+            None => self.message = Some("No imported finding on this line".to_string()),
         }
+    }
 
-        context
+    /// X: dismiss the imported finding on the cursor line
+    pub fn dismiss_pending(&mut self) {
+        match self.pending_at(self.cursor) {
+            Some(idx) => {
+                self.remove_comment(idx);
+                self.message = Some("Finding dismissed".to_string());
+            }
+            None => self.message = Some("No imported finding on this line".to_string()),
+        }
+    }
+
+    /// :accept all / :dismiss all
+    fn triage_all(&mut self, accept: bool) {
+        // Replies go with their thread rather than counting on their own
+        let pending: Vec<Uuid> = self
+            .comments
+            .iter()
+            .filter(|c| c.pending && comment::is_thread_root(c, &self.comments))
+            .map(|c| c.id)
+            .collect();
+        let count = pending.len();
+        if accept {
+            self.comments.iter_mut().for_each(|c| c.pending = false);
+        } else {
+            let dismissed = pending;
+            self.comments.retain(|c| {
+                !dismissed.contains(&c.id) && !c.in_reply_to.is_some_and(|r| dismissed.contains(&r))
+            });
+        }
+        self.message = Some(format!(
+            "{} {} finding{}",
+            if accept { "Accepted" } else { "Dismissed" },
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    /// Set the status of the thread on the cursor line
+    pub fn set_thread_status(&mut self, status: CommentStatus) {
+        match self.thread_root_at(self.cursor) {
+            Some(idx) => {
+                self.comments[idx].status = status;
+                self.message = Some(format!("Thread marked {}", status.label()));
+            }
+            None => self.message = Some("No comment thread on this line".to_string()),
+        }
+    }
+
+    /// Set the severity and/or category of the thread on the cursor line
+    fn set_thread_labels(&mut self, severity: Option<Severity>, category: Option<Category>) {
+        let Some(idx) = self.thread_root_at(self.cursor) else {
+            self.message = Some("No comment thread on this line".to_string());
+            return;
+        };
+
+        let comment = &mut self.comments[idx];
+        comment.severity = severity.or(comment.severity);
+        comment.category = category.or(comment.category);
+        self.message = Some(format!("Thread labels: {}", comment.labels().join(", ")));
+    }
+
+    /// s: cycle the severity of the thread on the cursor line
+    pub fn cycle_severity(&mut self) {
+        if let Some(idx) = self.thread_root_at(self.cursor) {
+            let comment = &mut self.comments[idx];
+            comment.severity = cycle(&Severity::ALL, comment.severity);
+            self.message = Some(format!(
+                "Severity: {}",
+                comment.severity.map_or("none", |s| s.label())
+            ));
+        }
+    }
+
+    /// S: cycle the category of the thread on the cursor line
+    pub fn cycle_category(&mut self) {
+        if let Some(idx) = self.thread_root_at(self.cursor) {
+            let comment = &mut self.comments[idx];
+            comment.category = cycle(&Category::ALL, comment.category);
+            self.message = Some(format!(
+                "Category: {}",
+                comment.category.map_or("none", |c| c.label())
+            ));
+        }
+    }
+
+    /// R: resolve the thread on the cursor line, or reopen it
+    pub fn toggle_thread_resolved(&mut self) {
+        let status = match self.thread_root_at(self.cursor) {
+            Some(idx) if self.comments[idx].status == CommentStatus::Open => {
+                CommentStatus::Resolved
+            }
+            _ => CommentStatus::Open,
+        };
+        self.set_thread_status(status);
     }
 
     pub fn delete_comment_at_cursor(&mut self) {
         if let Some(line) = self.diff_lines.get(self.cursor) {
-            if let Some(line_number) = line.new_line_no {
-                // Remove comment matching this file and line
-                self.comments
-                    .retain(|c| !(c.file_path == line.file_path && c.line_number == line_number));
-            }
+            // Remove comment matching this file and line
+            self.comments.retain(|c| !c.is_on(line));
+        }
+    }
+
+    /// Shown diff lines starting at the cursor, at most `count` of them
+    fn lines_from_cursor(&self, count: usize) -> Vec<usize> {
+        (self.cursor..self.diff_lines.len())
+            .filter(|&i| !self.is_hidden(i))
+            .take(count.max(1))
+            .collect()
+    }
+
+    /// dd: delete the comments on `count` lines starting at the cursor
+    pub fn delete_comments(&mut self, count: usize) {
+        let cursor = self.cursor;
+        for index in self.lines_from_cursor(count) {
+            self.cursor = index;
+            self.delete_comment_at_cursor();
         }
+        self.cursor = cursor;
+    }
+
+    /// yy: yank the comment on the cursor line, or else the code on `count`
+    /// lines without their diff markers
+    pub fn yank_lines(&mut self, count: usize) {
+        if let (Some(idx), 1) = (self.comment_index_at(self.cursor), count) {
+            self.register = self.comments[idx].text.clone();
+            self.message = Some("Yanked comment".to_string());
+            return;
+        }
+
+        let lines = self.lines_from_cursor(count);
+        self.register = lines
+            .iter()
+            .map(|&i| &self.diff_lines[i])
+            .map(|l| match l.line_type {
+                LineType::FileHeader | LineType::HunkHeader => l.content.as_str(),
+                _ => l.content.get(1..).unwrap_or(""),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.message = Some(format!(
+            "{} line{} yanked",
+            lines.len(),
+            if lines.len() == 1 { "" } else { "s" }
+        ));
     }
 
     pub fn search(&mut self, query: &str) {
@@ -187,7 +1228,7 @@ impl App {
 
         // Jump to first match
         if !self.search_matches.is_empty() {
-            self.cursor = self.search_matches[0];
+            self.jump_to(self.search_matches[0]);
         }
     }
 
@@ -197,7 +1238,7 @@ impl App {
         }
 
         self.current_match = (self.current_match + 1) % self.search_matches.len();
-        self.cursor = self.search_matches[self.current_match];
+        self.jump_to(self.search_matches[self.current_match]);
     }
 
     pub fn prev_match(&mut self) {
@@ -210,7 +1251,7 @@ impl App {
         } else {
             self.current_match - 1
         };
-        self.cursor = self.search_matches[self.current_match];
+        self.jump_to(self.search_matches[self.current_match]);
     }
 
     pub fn quit(&mut self) {
@@ -225,3 +1266,312 @@ impl App {
         };
     }
 }
+
+/// Next value in `all` after `current`, going back to none after the last
+fn cycle<T: Copy + PartialEq>(all: &[T], current: Option<T>) -> Option<T> {
+    match current {
+        None => all.first().copied(),
+        Some(value) => all
+            .iter()
+            .position(|v| *v == value)
+            .and_then(|i| all.get(i + 1))
+            .copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_HUNKS: &str = "\
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -2,7 +2,7 @@
+ l2
+ l3
+ l4
+-l5
++c5
+ l6
+ l7
+ l8
+@@ -27,7 +27,7 @@
+ l27
+ l28
+ l29
+-l30
++c30
+ l31
+ l32
+ l33
+";
+
+    fn app(diff: &str) -> App {
+        let diff_lines = diff::parse_diff(diff).unwrap();
+        App::new(
+            diff_lines,
+            Vec::new(),
+            ViewedState::default(),
+            String::new(),
+        )
+    }
+
+    fn line_index(app: &App, content: &str) -> usize {
+        app.diff_lines
+            .iter()
+            .position(|l| l.content == content)
+            .unwrap()
+    }
+
+    #[test]
+    fn context_stops_at_the_hunk_header() {
+        let mut app = app(TWO_HUNKS);
+        app.cursor = line_index(&app, " l28");
+        app.save_comment("x".to_string(), 1);
+
+        let context: Vec<&str> = app.comments[0]
+            .context
+            .iter()
+            .map(|c| c.content.as_str())
+            .collect();
+        assert_eq!(context, [" l27", " l28"]);
+    }
+
+    #[test]
+    fn suggestion_at_the_top_of_a_hunk_applies() {
+        let dir = std::env::temp_dir().join(format!("vr-app-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file: Vec<String> = (1..=40)
+            .map(|n| match n {
+                5 | 30 => format!("c{}", n),
+                _ => format!("l{}", n),
+            })
+            .collect();
+        std::fs::write(dir.join("f.txt"), file.join("\n") + "\n").unwrap();
+
+        let mut app = app(TWO_HUNKS);
+        app.cursor = line_index(&app, " l28");
+        app.save_comment("rename\n```suggestion\nL28\n```".to_string(), 1);
+        let report = suggestion::apply_suggestions(&mut app.comments, &dir);
+        let patched = std::fs::read_to_string(dir.join("f.txt")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.summary(), "Applied 1 suggestion");
+        assert_eq!(patched.lines().nth(27), Some("L28"));
+        assert_eq!(patched.lines().count(), 40);
+    }
+
+    #[test]
+    fn commit_message_takes_no_suggestions() {
+        let mut app = app("\
+commit 0123456789abcdef0123456789abcdef01234567
+Author: A U Thor <author@example.com>
+
+    Subject line
+
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -1 +1 @@
+-a
++b
+");
+        app.cursor = app
+            .diff_lines
+            .iter()
+            .position(|l| l.file_path == diff::COMMIT_MSG && l.new_line_no == Some(1))
+            .unwrap();
+        app.start_suggestion(1);
+        assert!(matches!(app.mode, Mode::Normal));
+
+        // Nor does one written out by hand get applied
+        app.save_comment("```suggestion\nSubject\n```".to_string(), 1);
+        assert!(app.comments[0].suggestion.is_some());
+        let dir = std::env::temp_dir().join(format!("vr-app-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let report = suggestion::apply_suggestions(&mut app.comments, &dir);
+        let written = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((report.applied, report.conflicts.len()), (0, 0));
+        assert_eq!(written, 0);
+    }
+
+    #[test]
+    fn folds_hide_lines_until_opened() {
+        let mut app = app(TWO_HUNKS);
+        let second_hunk = app.files[0].hunks[1].start;
+        app.cursor = line_index(&app, " l28");
+
+        app.close_fold_at_cursor();
+        assert_eq!(app.cursor, second_hunk);
+        assert_eq!(app.visible_lines().last(), Some(&second_hunk));
+        assert!(app.is_hidden(second_hunk + 1));
+
+        app.close_all_folds();
+        assert_eq!(app.visible_lines(), [0]);
+        assert_eq!(app.cursor, 0);
+
+        app.open_all_folds();
+        assert_eq!(app.visible_lines().len(), app.diff_lines.len());
+        assert_eq!(app.visible_row(second_hunk), Some(second_hunk));
+    }
+
+    #[test]
+    fn clearing_an_edited_comment_deletes_its_thread() {
+        let mut app = app(TWO_HUNKS);
+        app.cursor = line_index(&app, " l28");
+        app.save_comment("x".to_string(), 1);
+        let reply = app.comments[0].reply("y".to_string());
+        app.comments.push(reply);
+
+        app.mode = Mode::Insert {
+            editor: CommentEditor::new(" \n".to_string()),
+            target: InsertTarget::Edit(0),
+        };
+        app.exit_to_normal_mode();
+        assert!(app.comments.is_empty());
+    }
+
+    #[test]
+    fn label_commands_and_cycling() {
+        let mut app = app(TWO_HUNKS);
+        app.cursor = line_index(&app, " l28");
+        app.save_comment("x".to_string(), 1);
+
+        app.execute_command("severity");
+        assert_eq!(
+            app.message.as_deref(),
+            Some("Severity needs a level: nit, suggestion, issue, blocker")
+        );
+        app.execute_command("severity bogus");
+        assert_eq!(app.message.as_deref(), Some("Unknown severity: bogus"));
+        app.execute_command("severity Blocker");
+        app.execute_command("category performance");
+        assert_eq!(app.message.as_deref(), Some("Thread labels: blocker, perf"));
+
+        // Cycling goes through every label, then back to none
+        app.cycle_severity();
+        assert_eq!(app.comments[0].severity, None);
+        app.cycle_severity();
+        assert_eq!(app.comments[0].severity, Some(Severity::Nit));
+        for _ in Category::ALL {
+            app.cycle_category();
+        }
+        assert_eq!(app.comments[0].category, Some(Category::Security));
+        assert_eq!(app.message.as_deref(), Some("Category: security"));
+    }
+
+    #[test]
+    fn accepted_imports_export_with_their_replies() {
+        let mut app = app(TWO_HUNKS);
+        let root = Comment::new("f.txt".into(), 28, "Finding".into(), Vec::new());
+        let reply = root.reply("Seen before".into());
+        let other = Comment::new("f.txt".into(), 3, "Other finding".into(), Vec::new());
+        assert_eq!(app.import_comments(vec![root, reply, other]), (3, 0));
+        assert_eq!(
+            app.comments.iter().map(|c| c.pending).collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        assert!(CommentFilter::default().apply(&app.comments).is_empty());
+
+        app.cursor = line_index(&app, " l28");
+        app.accept_pending();
+        app.execute_command("dismiss all");
+        assert_eq!(app.message.as_deref(), Some("Dismissed 1 finding"));
+
+        let exported = CommentFilter::default().apply(&app.comments);
+        let texts: Vec<&str> = exported.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["Finding", "Seen before"]);
+        let values = export::comment_values(&exported).unwrap();
+        assert_eq!(values[1]["text"], "Seen before");
+    }
+
+    #[test]
+    fn hunk_and_file_motions_stop_at_the_ends() {
+        let mut app = app(include_str!("../tests/fixtures/review.diff"));
+        let (lib, main) = (app.files[0].clone(), app.files[1].clone());
+
+        app.next_hunk();
+        assert_eq!(app.cursor, lib.hunks[0].start);
+        app.next_hunk();
+        app.next_hunk();
+        assert_eq!(app.cursor, main.hunks[0].start);
+        // No wrapping around past the last hunk
+        app.next_hunk();
+        assert_eq!(app.cursor, main.hunks[0].start);
+        app.prev_hunk();
+        assert_eq!(app.cursor, lib.hunks[1].start);
+
+        app.next_file();
+        assert_eq!(app.cursor, main.start);
+        app.next_file();
+        assert_eq!(app.cursor, main.start);
+        app.prev_file();
+        app.prev_file();
+        assert_eq!(app.cursor, lib.start);
+    }
+
+    #[test]
+    fn comment_motions_open_closed_folds() {
+        let mut app = app(include_str!("../tests/fixtures/review.diff"));
+        let target = line_index(&app, "+fn y() -> u8 { 2 }");
+        app.cursor = target;
+        app.save_comment("Why 2?".to_string(), 1);
+        app.close_all_folds();
+
+        app.next_comment();
+        assert_eq!(app.cursor, target);
+        assert!(!app.is_hidden(target));
+
+        app.move_to_bottom();
+        app.prev_comment();
+        assert_eq!(app.cursor, target);
+        app.prev_comment();
+        assert_eq!(app.cursor, target);
+    }
+
+    #[test]
+    fn unreviewed_file_motion_skips_viewed_files() {
+        let fixture = include_str!("../tests/fixtures/review.diff");
+        let mut app = app(&format!(
+            "{}diff --git a/README b/README\n--- a/README\n+++ b/README\n@@ -1 +1 @@\n-a\n+b\n",
+            fixture
+        ));
+        let main = app.files[1].clone();
+        app.viewed.toggle_file(&main);
+
+        app.next_unreviewed_file();
+        assert_eq!(app.cursor, app.files[2].start);
+        app.next_unreviewed_file();
+        assert_eq!(app.cursor, app.files[2].start);
+    }
+
+    #[test]
+    fn reload_keeps_the_cursor_comments_and_folds() {
+        let fixture = include_str!("../tests/fixtures/review.diff");
+        let mut app = app(fixture);
+        app.cursor = line_index(&app, "+fn y() -> u8 { 2 }");
+        app.save_comment("Why 2?".to_string(), 1);
+        app.cursor = app.files[1].start;
+        app.close_fold_at_cursor();
+        app.cursor = line_index(&app, " fn z() {}");
+        app.close_fold_at_cursor();
+        app.cursor = line_index(&app, " fn c() {}");
+
+        // A line was added at the top of src/lib.rs since
+        let changed = fixture
+            .replace("@@ -1,3 +1,5 @@\n", "@@ -1,3 +1,6 @@\n+use std::fmt;\n")
+            .replace("@@ -20,3 +22,3 @@", "@@ -20,3 +23,3 @@");
+        let moved = app.reload_diff(diff::parse_diff(&changed).unwrap());
+
+        assert_eq!(app.cursor, line_index(&app, " fn c() {}"));
+        assert_eq!(app.diff_lines[app.cursor].new_line_no, Some(6));
+        assert_eq!(moved, 1);
+        assert_eq!(app.comments[0].line_number, 24);
+        let main = app.files[1].start;
+        assert!(app.is_hidden(main + 1));
+        assert!(app.is_hidden(app.files[0].hunks[1].start + 1));
+        assert!(!app.is_hidden(app.files[0].hunks[0].start + 1));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::comment::{Category, CommentFilter, Severity};
+use crate::export::Format;
+use anyhow::{Context, Result};
+
+/// What vr was asked to do
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Review,
+    Apply(Option<String>), // Apply suggestions from this session, or the current one
+    Quickfix,              // Write the session's comments as a quickfix list
+    Serve,                 // Answer MCP / JSON-RPC requests on stdin
+}
+
+/// Command line options
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub vcs_ref: Option<String>, // Commit, changeset or range to review
+    pub author: Option<String>,  // Overrides the author read from VCS config
+    pub filter: CommentFilter,   // Which comments are exported
+    pub format: Format,          // Format of the output file
+    pub imports: Vec<String>,    // Files of findings to triage
+    pub on_save: Option<String>, // Hook commands, overriding the config file
+    pub on_quit: Option<String>,
+    pub watch: bool,             // Reload the diff when files change
+    pub interdiff: bool,         // Review what changed since an older version
+    pub old_ref: Option<String>, // That version, or the session's last one
+    pub range_diff: bool,        // Compare two versions of a patch series
+}
+
+/// Parse command line arguments (without the program name)
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    let mut second_ref = None; // Only --interdiff and --range-diff take two refs
+    let mut review_flag = None; // First option vr serve would ignore
+
+    while let Some(arg) = args.next() {
+        // Accept both "--flag value" and "--flag=value"
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| -> Result<String> {
+            match inline_value {
+                Some(value) => Ok(value.to_string()),
+                None => args
+                    .next()
+                    .with_context(|| format!("{} requires a value", name)),
+            }
+        };
+
+        if flag.starts_with("--") && !matches!(flag.as_str(), "--author" | "--stdio") {
+            review_flag.get_or_insert_with(|| flag.clone());
+        }
+
+        match flag.as_str() {
+            "--author" => parsed.author = Some(value("--author")?),
+            "--severity" => {
+                let level = value("--severity")?;
+                parsed.filter.min_severity = Some(
+                    Severity::parse(&level)
+                        .with_context(|| format!("Unknown severity: {}", level))?,
+                );
+            }
+            "--category" => {
+                let name = value("--category")?;
+                parsed.filter.categories.push(
+                    Category::parse(&name)
+                        .with_context(|| format!("Unknown category: {}", name))?,
+                );
+            }
+            "--import" => parsed.imports.push(value("--import")?),
+            "--on-save" => parsed.on_save = Some(value("--on-save")?),
+            "--on-quit" => parsed.on_quit = Some(value("--on-quit")?),
+            "--watch" => parsed.watch = true,
+            "--interdiff" => parsed.interdiff = true,
+            "--range-diff" => parsed.range_diff = true,
+            "--quickfix" => {
+                parsed.command = Command::Quickfix;
+                parsed.format = Format::Quickfix;
+            }
+            "--template" => parsed.format = Format::Prompt(value("--template")?),
+            // stdio is the only transport so far
+            "--stdio" if parsed.command == Command::Serve => {}
+            "--format" => {
+                let name = value("--format")?;
+                parsed.format =
+                    Format::parse(&name).with_context(|| format!("Unknown format: {}", name))?;
+            }
+            _ if flag.starts_with("--") => anyhow::bail!("Unknown option: {}", flag),
+            "apply" if parsed.command == Command::Review && parsed.vcs_ref.is_none() => {
+                parsed.command = Command::Apply(None)
+            }
+            "serve" if parsed.command == Command::Review && parsed.vcs_ref.is_none() => {
+                parsed.command = Command::Serve
+            }
+            _ if parsed.command == Command::Apply(None) => {
+                parsed.command = Command::Apply(Some(arg))
+            }
+            _ if matches!(parsed.command, Command::Review | Command::Serve)
+                && parsed.vcs_ref.is_none() =>
+            {
+                parsed.vcs_ref = Some(arg)
+            }
+            _ if parsed.command == Command::Review && second_ref.is_none() => {
+                second_ref = Some(arg)
+            }
+            _ => anyhow::bail!("Unexpected argument: {}", arg),
+        }
+    }
+
+    // A branch or tag named like a subcommand needs its full name, e.g.
+    // refs/heads/serve
+    if let (Command::Serve, Some(flag)) = (&parsed.command, review_flag) {
+        anyhow::bail!("{} doesn't apply to vr serve", flag);
+    }
+
+    if parsed.interdiff || parsed.range_diff {
+        let flag = if parsed.interdiff {
+            "--interdiff"
+        } else {
+            "--range-diff"
+        };
+        if parsed.interdiff && parsed.range_diff {
+            anyhow::bail!("--interdiff can't be combined with --range-diff");
+        }
+        if parsed.command != Command::Review {
+            anyhow::bail!("{} only works when reviewing", flag);
+        }
+        if parsed.watch {
+            anyhow::bail!("{} can't be combined with --watch", flag);
+        }
+        if parsed.range_diff && parsed.vcs_ref.is_none() {
+            anyhow::bail!("--range-diff needs a range, e.g. vr --range-diff main...feature");
+        }
+        // "old new"; with one ref it's the new version (or for
+        // --range-diff, an A...B range covering both)
+        if second_ref.is_some() {
+            parsed.old_ref = std::mem::replace(&mut parsed.vcs_ref, second_ref);
+        }
+    } else if let Some(arg) = second_ref {
+        anyhow::bail!("Unexpected argument: {}", arg);
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Args {
+        parse(args.iter().map(|a| a.to_string())).unwrap()
+    }
+
+    #[test]
+    fn formats_and_templates() {
+        assert_eq!(parse_args(&["--format", "qf"]).format, Format::Quickfix);
+        assert_eq!(
+            parse_args(&["--format", "prompt"]).format,
+            Format::Prompt(crate::export::DEFAULT_TEMPLATE.to_string())
+        );
+        assert_eq!(
+            parse_args(&["HEAD", "--template", "fix-all"]).format,
+            Format::Prompt("fix-all".to_string())
+        );
+        assert!(parse(["--format".to_string(), "nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn subcommands_and_refs() {
+        let args = parse_args(&["serve", "--stdio", "--author", "bot", "main...HEAD"]);
+        assert_eq!(args.command, Command::Serve);
+        assert_eq!(args.vcs_ref.as_deref(), Some("main...HEAD"));
+
+        for flag in [
+            "--watch",
+            "--import=x.sarif",
+            "--on-save=true",
+            "--format=json",
+        ] {
+            let err = parse(["serve", flag].map(String::from)).unwrap_err();
+            assert!(
+                err.to_string().ends_with("doesn't apply to vr serve"),
+                "{}",
+                flag
+            );
+        }
+
+        // A branch named like a subcommand is given in full
+        let args = parse_args(&["refs/heads/serve", "--watch"]);
+        assert_eq!(args.command, Command::Review);
+        assert_eq!(args.vcs_ref.as_deref(), Some("refs/heads/serve"));
+    }
+}
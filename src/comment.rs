@@ -1,22 +1,249 @@
-use anyhow::Result;
+use crate::diff::{self, DiffLine};
+use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ContextLine {
     pub content: String, // Line content including the +/- prefix
     pub prefix: String,  // Deprecated: prefix is already in content
+    #[serde(default)]
+    pub old_line_no: Option<usize>,
+    #[serde(default)]
+    pub new_line_no: Option<usize>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A previous version of a comment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommentEdit {
+    pub text: String,
+    pub timestamp: DateTime<Utc>, // When this version was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<Suggestion>,
+}
+
+/// Resolution state of a comment thread
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentStatus {
+    #[default]
+    Open,
+    Resolved,
+    WontFix,
+}
+
+impl CommentStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CommentStatus::Open => "open",
+            CommentStatus::Resolved => "resolved",
+            CommentStatus::WontFix => "wontfix",
+        }
+    }
+}
+
+/// How much a comment matters, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Nit,
+    Suggestion,
+    Issue,
+    Blocker,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Nit,
+        Severity::Suggestion,
+        Severity::Issue,
+        Severity::Blocker,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Nit => "nit",
+            Severity::Suggestion => "suggestion",
+            Severity::Issue => "issue",
+            Severity::Blocker => "blocker",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|sev| sev.label().eq_ignore_ascii_case(s.trim()))
+    }
+}
+
+/// What area a comment is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Security,
+    Perf,
+    Style,
+    Tests,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Security,
+        Category::Perf,
+        Category::Style,
+        Category::Tests,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Security => "security",
+            Category::Perf => "perf",
+            Category::Style => "style",
+            Category::Tests => "tests",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "performance" => Some(Category::Perf),
+            "test" => Some(Category::Tests),
+            s => Self::ALL.into_iter().find(|cat| cat.label() == s),
+        }
+    }
+}
+
+/// Split leading `[label]` tags such as `[blocker][security]` off a comment
+///
+/// Parsing stops at the first tag that isn't a known severity or category,
+/// so text like `[WIP]` stays part of the comment.
+pub fn parse_labels(text: &str) -> (Option<Severity>, Option<Category>, &str) {
+    let mut severity = None;
+    let mut category = None;
+    let mut rest = text.trim_start();
+
+    while let Some(tag) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        let (label, after) = tag;
+        if let Some(sev) = Severity::parse(label) {
+            severity = Some(sev);
+        } else if let Some(cat) = Category::parse(label) {
+            category = Some(cat);
+        } else {
+            break;
+        }
+        rest = after.trim_start();
+    }
+
+    (severity, category, rest)
+}
+
+/// Replacement text proposed for the lines a comment is anchored to
+///
+/// The replaced range starts at the comment's `line_number` and ends at
+/// `end_line`, both in new-file numbering.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub end_line: usize,
+    pub original: Vec<String>, // Lines being replaced, as they were when suggested
+    pub replacement: String,
+}
+
+const SUGGESTION_FENCE: &str = "```suggestion";
+
+/// Split a ```suggestion fenced block out of a comment's text
+///
+/// Returns the text without the block and the block's contents, if there
+/// was one. An empty block suggests deleting the lines.
+pub fn split_suggestion(text: &str) -> (String, Option<String>) {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(open) = lines.iter().position(|l| l.trim() == SUGGESTION_FENCE) else {
+        return (text.to_string(), None);
+    };
+    let close = lines[open + 1..]
+        .iter()
+        .position(|l| l.trim() == "```")
+        .map_or(lines.len(), |i| open + 1 + i);
+
+    let replacement = lines[open + 1..close].join("\n");
+    let rest: Vec<&str> = lines[..open]
+        .iter()
+        .chain(lines.get(close + 1..).unwrap_or_default())
+        .copied()
+        .collect();
+    (rest.join("\n").trim().to_string(), Some(replacement))
+}
+
+/// Which comments make it into the exported output
+#[derive(Debug, Clone, Default)]
+pub struct CommentFilter {
+    pub min_severity: Option<Severity>,
+    pub categories: Vec<Category>,
+}
+
+impl CommentFilter {
+    /// Whether a thread root is exported; pending imports never are
+    pub fn matches(&self, comment: &Comment) -> bool {
+        if comment.pending {
+            return false;
+        }
+        let severity_ok = match self.min_severity {
+            Some(min) => comment.severity.is_some_and(|sev| sev >= min),
+            None => true,
+        };
+        let category_ok = self.categories.is_empty()
+            || comment
+                .category
+                .is_some_and(|cat| self.categories.contains(&cat));
+        severity_ok && category_ok
+    }
+
+    /// Threads whose root matches, with their replies
+    pub fn apply(&self, comments: &[Comment]) -> Vec<Comment> {
+        comments
+            .iter()
+            .filter(|c| {
+                let root = match c.in_reply_to {
+                    Some(id) => comments.iter().find(|r| r.id == id).unwrap_or(c),
+                    None => c,
+                };
+                self.matches(root)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Comment {
+    #[serde(default)]
+    pub id: Uuid, // Nil until load_comments_from fills it in for older sessions
+    #[serde(default)]
+    pub in_reply_to: Option<Uuid>, // Thread root this comment answers
+    #[serde(default)]
+    pub status: CommentStatus, // Only meaningful on thread roots
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    #[serde(default)]
+    pub category: Option<Category>,
     pub file_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>, // New commit of the range-diff pair it was made on
     pub line_number: usize,
     pub text: String,
     pub timestamp: DateTime<Utc>,
     pub context: Vec<ContextLine>, // Lines of context around the comment
+    #[serde(default)]
+    pub edited_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub history: Vec<CommentEdit>, // Earlier versions, oldest first
+    #[serde(default)]
+    pub suggestion: Option<Suggestion>,
+    #[serde(default)]
+    pub pending: bool, // Imported finding that hasn't been accepted yet
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,31 +251,136 @@ struct CommentStorage {
     comments: Vec<Comment>,
 }
 
-const COMMENT_DIR: &str = ".vim-review";
-const COMMENT_FILE: &str = ".vim-review/comments.json";
+pub const COMMENT_FILE: &str = ".vim-review/comments.json";
 
 /// Load comments from disk
 pub fn load_comments() -> Result<Vec<Comment>> {
-    if !Path::new(COMMENT_FILE).exists() {
+    load_comments_from(Path::new(COMMENT_FILE))
+}
+
+/// Load comments from a session file, e.g. another checkout's
+/// .vim-review/comments.json
+pub fn load_comments_from(path: &Path) -> Result<Vec<Comment>> {
+    if !path.exists() {
         return Ok(Vec::new());
     }
 
-    let content = fs::read_to_string(COMMENT_FILE)?;
-    let storage: CommentStorage = serde_json::from_str(&content)?;
+    // A file that doesn't parse is an error rather than an empty session,
+    // which the next save would write over
+    let content = fs::read_to_string(path)?;
+    let mut storage: CommentStorage =
+        serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?;
+
+    // Sessions from before comments had ids get one derived from the
+    // comment, so it's the same every time the file is read
+    for c in storage.comments.iter_mut().filter(|c| c.id.is_nil()) {
+        let line = c.line_number.to_string();
+        let timestamp = c.timestamp.to_rfc3339();
+        let hash = diff::content_hash([c.file_path.as_str(), &line, &timestamp, &c.text]);
+        c.id = Uuid::from_u64_pair(hash, hash.rotate_left(32));
+    }
     Ok(storage.comments)
 }
 
 /// Save comments to disk
 pub fn save_comments(comments: &[Comment]) -> Result<()> {
+    save_comments_to(comments, Path::new(COMMENT_FILE))
+}
+
+/// Save one review's comments to the session, merged with what it holds
+/// now, and return the review's comments as saved
+///
+/// `loaded` is the review's comments as they were last read or written.
+/// The session also holds comments from other reviews, and `vr serve` may
+/// have replied to or resolved comments since; see [`merge_review`].
+pub fn save_review(comments: &[Comment], loaded: &[Comment]) -> Result<Vec<Comment>> {
+    let (session, review) = merge_review(comments, loaded, load_comments()?);
+    save_comments(&session)?;
+    Ok(review)
+}
+
+/// The session's `stored` versions of a review's comments, to merge the
+/// review with later; a comment moved since it was read, as --interdiff
+/// moves them, then counts as changed in the review
+pub fn stored_versions(review: &[Comment], stored: &[Comment]) -> Vec<Comment> {
+    stored
+        .iter()
+        .filter(|c| review.iter().any(|r| r.id == c.id))
+        .cloned()
+        .collect()
+}
+
+/// Merge a review's comments into the session's `stored` comments, giving
+/// the whole session and the review's part of it
+///
+/// A comment changed only in the session keeps that change, one changed in
+/// the review wins, and one deleted on either side without being changed on
+/// the other is dropped. Comments new to the session join the review when
+/// they reply to one of its threads; the rest belong to other reviews.
+pub fn merge_review(
+    comments: &[Comment],
+    loaded: &[Comment],
+    stored: Vec<Comment>,
+) -> (Vec<Comment>, Vec<Comment>) {
+    let by_id = |list: &[Comment], id: Uuid| list.iter().position(|c| c.id == id);
+
+    let mut review: Vec<Comment> = Vec::new();
+    for c in comments {
+        let base = by_id(loaded, c.id).map(|idx| &loaded[idx]);
+        let theirs = by_id(&stored, c.id).map(|idx| &stored[idx]);
+        match (base, theirs) {
+            (Some(base), Some(theirs)) if base == c => review.push(theirs.clone()),
+            (Some(base), None) if base == c => {}
+            _ => review.push(c.clone()),
+        }
+    }
+
+    let mut session = Vec::new();
+    for c in stored {
+        if by_id(&review, c.id).is_some() {
+            continue;
+        }
+        match by_id(loaded, c.id) {
+            // Deleted in the review; a change made meanwhile keeps it
+            Some(idx) if loaded[idx] == c => {}
+            Some(_) => review.push(c),
+            None if c
+                .in_reply_to
+                .is_some_and(|root| by_id(&review, root).is_some()) =>
+            {
+                review.push(c)
+            }
+            None => session.push(c),
+        }
+    }
+
+    session.extend(review.iter().cloned());
+    (session, review)
+}
+
+/// Whether a comment is on a line of this diff; the session's other
+/// comments are from reviews of other changes
+pub fn in_diff(comment: &Comment, diff: &[DiffLine]) -> bool {
+    diff.iter().any(|l| comment.is_on(l))
+}
+
+/// Save comments to a session file
+pub fn save_comments_to(comments: &[Comment], path: &Path) -> Result<()> {
     // Create directory if it doesn't exist
-    fs::create_dir_all(COMMENT_DIR)?;
+    if let Some(parent) = path.parent() {
+        crate::session::create_dir(parent)?;
+    }
 
     let storage = CommentStorage {
         comments: comments.to_vec(),
     };
 
+    // Written next to the file and renamed over it, so `vr serve` reading
+    // the session never sees half of it
     let json = serde_json::to_string_pretty(&storage)?;
-    fs::write(COMMENT_FILE, json)?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, path)?;
 
     Ok(())
 }
@@ -64,8 +396,18 @@ pub fn save_comments_to_file(comments: &[Comment], file_path: &str) -> Result<()
 
     let mut output = String::new();
 
-    for comment in comments {
-        output.push_str(&format!("{}:{}\n", comment.file_path, comment.line_number));
+    for comment in comments.iter().filter(|c| is_thread_root(c, comments)) {
+        output.push_str(&format!("{}:{}", comment.file_path, comment.line_number));
+        for label in comment.labels() {
+            output.push_str(&format!(" [{}]", label));
+        }
+        if comment.status != CommentStatus::Open {
+            output.push_str(&format!(" [{}]", comment.status.label()));
+        }
+        if let Some(author) = &comment.author {
+            output.push_str(&format!(" ({})", author));
+        }
+        output.push('\n');
 
         // Add context lines with visual separators
         if !comment.context.is_empty() {
@@ -82,7 +424,24 @@ pub fn save_comments_to_file(comments: &[Comment], file_path: &str) -> Result<()
             output.push_str("----------------------------------------------------------\n");
         }
 
-        output.push_str(&format!("{}\n\n", comment.text));
+        output.push_str(&format!("{}\n", comment.body()));
+
+        // Replies follow their thread, indented under it
+        for reply in replies_to(comment, comments) {
+            let author = reply
+                .author
+                .as_ref()
+                .map(|a| format!("{}: ", a))
+                .unwrap_or_default();
+            for (n, line) in reply.text.lines().enumerate() {
+                if n == 0 {
+                    output.push_str(&format!("  ↳ {}{}\n", author, line));
+                } else {
+                    output.push_str(&format!("    {}\n", line));
+                }
+            }
+        }
+        output.push('\n');
     }
 
     let mut file = fs::File::create(file_path)?;
@@ -91,6 +450,63 @@ pub fn save_comments_to_file(comments: &[Comment], file_path: &str) -> Result<()
     Ok(())
 }
 
+/// Whether the comment starts a thread; replies whose root is gone are
+/// treated as roots so they aren't lost
+pub fn is_thread_root(comment: &Comment, comments: &[Comment]) -> bool {
+    match comment.in_reply_to {
+        None => true,
+        Some(root) => !comments.iter().any(|c| c.id == root),
+    }
+}
+
+/// How far a re-anchored comment may jump to a line with its own text when
+/// the code around it changed
+const NEARBY_LINES: usize = 3;
+
+/// Move comments onto a new diff of their files, given the line each one is
+/// expected at (`expected[i]` for `comments[i]`); returns how many moved
+///
+/// The code a comment was written on, if it is still there, beats the
+/// expected line, and the commented line alone does if it is close by.
+pub fn reanchor(comments: &mut [Comment], diff: &[DiffLine], expected: &[usize]) -> usize {
+    let mut moved = 0;
+    for (c, &expected) in comments.iter_mut().zip(expected) {
+        let context: Vec<&str> = c
+            .context
+            .iter()
+            .filter(|ctx| !ctx.content.starts_with('-'))
+            .map(|ctx| ctx.content.get(1..).unwrap_or(""))
+            .collect();
+        let line = diff::find_line(diff, &c.file_path, expected, &context)
+            .or_else(|| {
+                let own_line = &context[context.len().saturating_sub(1)..];
+                diff::find_line(diff, &c.file_path, expected, own_line)
+                    .filter(|line| line.abs_diff(expected) <= NEARBY_LINES)
+            })
+            .unwrap_or(expected)
+            .max(1);
+
+        if line != c.line_number {
+            if let Some(s) = &mut c.suggestion {
+                s.end_line = (s.end_line + line).saturating_sub(c.line_number);
+            }
+            c.line_number = line;
+            moved += 1;
+        }
+    }
+    moved
+}
+
+/// Replies to the given thread root, in the order they were written
+pub fn replies_to<'a>(
+    root: &'a Comment,
+    comments: &'a [Comment],
+) -> impl Iterator<Item = &'a Comment> {
+    comments
+        .iter()
+        .filter(move |c| c.in_reply_to == Some(root.id))
+}
+
 impl Comment {
     pub fn new(
         file_path: String,
@@ -99,11 +515,313 @@ impl Comment {
         context: Vec<ContextLine>,
     ) -> Self {
         Self {
+            id: Uuid::new_v4(),
+            in_reply_to: None,
+            status: CommentStatus::Open,
+            author: None,
+            severity: None,
+            category: None,
             file_path,
+            commit: None,
             line_number,
             text,
             timestamp: Utc::now(),
             context,
+            edited_at: None,
+            history: Vec::new(),
+            suggestion: None,
+            pending: false,
+        }
+    }
+
+    /// Move `[severity]`/`[category]` tags at the start of the text into the
+    /// comment's labels
+    pub fn apply_label_tags(&mut self) {
+        let (severity, category, rest) = parse_labels(&self.text);
+        if severity.is_none() && category.is_none() {
+            return;
         }
+
+        self.severity = severity.or(self.severity);
+        self.category = category.or(self.category);
+        self.text = rest.to_string();
+    }
+
+    /// Text followed by the suggested change as a ```suggestion block
+    pub fn body(&self) -> String {
+        match &self.suggestion {
+            Some(s) if self.text.is_empty() => {
+                format!("{}\n{}\n```", SUGGESTION_FENCE, s.replacement)
+            }
+            Some(s) => format!(
+                "{}\n\n{}\n{}\n```",
+                self.text, SUGGESTION_FENCE, s.replacement
+            ),
+            None => self.text.clone(),
+        }
+    }
+
+    /// Severity and category labels that are set, e.g. ["blocker", "security"]
+    pub fn labels(&self) -> Vec<&'static str> {
+        self.severity
+            .map(|s| s.label())
+            .into_iter()
+            .chain(self.category.map(|c| c.label()))
+            .collect()
+    }
+
+    /// Author's name without the email address
+    pub fn author_name(&self) -> Option<&str> {
+        let author = self.author.as_deref()?;
+        Some(author.split(" <").next().unwrap_or(author).trim())
+    }
+
+    /// Whether the comment is anchored to `line`: the same line of the same
+    /// file, in the same range-diff commit if any
+    pub fn is_on(&self, line: &DiffLine) -> bool {
+        self.file_path == line.file_path
+            && self.commit == line.commit
+            && line.new_line_no == Some(self.line_number)
+    }
+
+    /// A reply to this comment, anchored to the same line
+    pub fn reply(&self, text: String) -> Self {
+        Self {
+            in_reply_to: Some(self.id),
+            commit: self.commit.clone(),
+            ..Self::new(
+                self.file_path.clone(),
+                self.line_number,
+                text,
+                self.context.clone(),
+            )
+        }
+    }
+
+    /// Replace the text, keeping the previous version in the edit history
+    ///
+    /// Leading `[severity]`/`[category]` tags update the labels instead of
+    /// becoming part of the text. On a suggestion, the ```suggestion block
+    /// updates the replacement and removing the block drops the suggestion.
+    /// Any change to the text, the suggestion or the labels is an edit.
+    pub fn edit(&mut self, text: String) {
+        let state = |c: &Self| (c.text.clone(), c.suggestion.clone(), c.severity, c.category);
+        let before = state(self);
+
+        let (severity, category, rest) = parse_labels(&text);
+        self.severity = severity.or(self.severity);
+        self.category = category.or(self.category);
+
+        let text = match self.suggestion.take() {
+            Some(mut suggestion) => {
+                let (text, replacement) = split_suggestion(rest);
+                if let Some(replacement) = replacement {
+                    suggestion.replacement = replacement;
+                    self.suggestion = Some(suggestion);
+                }
+                text
+            }
+            None => rest.to_string(),
+        };
+        self.text = text;
+        if state(self) == before {
+            return;
+        }
+
+        let (text, suggestion, ..) = before;
+        self.history.push(CommentEdit {
+            text,
+            timestamp: self.edited_at.unwrap_or(self.timestamp),
+            suggestion,
+        });
+        self.edited_at = Some(Utc::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(text: &str) -> Comment {
+        Comment::new("src/lib.rs".to_string(), 3, text.to_string(), Vec::new())
+    }
+
+    fn texts(comments: &[Comment]) -> Vec<&str> {
+        comments.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_keeps_changes_made_elsewhere() {
+        let root = comment("root");
+        let loaded = vec![root.clone()];
+
+        // vr serve resolved the thread and replied while the TUI was open
+        let mut resolved = root.clone();
+        resolved.status = CommentStatus::Resolved;
+        let reply = root.reply("done".to_string());
+        let other_review = comment("elsewhere");
+        let stored = vec![other_review, resolved, reply];
+
+        let (session, review) = merge_review(&loaded, &loaded, stored);
+        assert_eq!(texts(&review), ["root", "done"]);
+        assert_eq!(review[0].status, CommentStatus::Resolved);
+        assert_eq!(texts(&session), ["elsewhere", "root", "done"]);
+    }
+
+    #[test]
+    fn merge_prefers_the_review_when_both_changed() {
+        let root = comment("root");
+        let loaded = vec![root.clone()];
+
+        let mut ours = root.clone();
+        ours.edit("edited here".to_string());
+        let mut theirs = root.clone();
+        theirs.status = CommentStatus::WontFix;
+
+        let (session, review) = merge_review(&[ours], &loaded, vec![theirs]);
+        assert_eq!(texts(&review), ["edited here"]);
+        assert_eq!(review[0].status, CommentStatus::Open);
+        assert_eq!(session, review);
+    }
+
+    #[test]
+    fn merge_deletions() {
+        let kept = comment("kept");
+        let deleted_here = comment("deleted here");
+        let deleted_there = comment("deleted there");
+        let loaded = vec![kept.clone(), deleted_here.clone(), deleted_there.clone()];
+
+        let (session, _) = merge_review(
+            &[kept.clone(), deleted_there],
+            &loaded,
+            vec![kept, deleted_here],
+        );
+        assert_eq!(texts(&session), ["kept"]);
+
+        // A comment deleted here but changed elsewhere meanwhile survives
+        let root = comment("root");
+        let mut resolved = root.clone();
+        resolved.status = CommentStatus::Resolved;
+        let (_, review) = merge_review(&[], &[root], vec![resolved]);
+        assert_eq!(texts(&review), ["root"]);
+    }
+
+    #[test]
+    fn edits_keep_earlier_texts() {
+        let mut c = comment("first");
+        let id = c.id;
+        c.edit("second".to_string());
+        c.edit("second".to_string());
+
+        assert_eq!((c.id, c.text.as_str()), (id, "second"));
+        assert_eq!(c.history.len(), 1);
+        assert_eq!(c.history[0].text, "first");
+        assert_eq!(c.history[0].timestamp, c.timestamp);
+        assert!(c.edited_at.is_some());
+
+        let edited_at = c.edited_at;
+        c.edit("[nit] third".to_string());
+        assert_eq!(
+            (c.text.as_str(), c.severity),
+            ("third", Some(Severity::Nit))
+        );
+        assert_eq!(c.history[1].text, "second");
+        assert_eq!(Some(c.history[1].timestamp), edited_at);
+    }
+
+    #[test]
+    fn suggestion_only_edits_are_recorded() {
+        let mut c = comment("rename");
+        let first = Suggestion {
+            end_line: 3,
+            original: vec!["let x = 1;".to_string()],
+            replacement: "let y = 1;".to_string(),
+        };
+        c.suggestion = Some(first.clone());
+
+        c.edit("rename\n\n```suggestion\nlet z = 1;\n```".to_string());
+        assert_eq!(c.suggestion.as_ref().unwrap().replacement, "let z = 1;");
+        assert_eq!(c.text, "rename");
+        assert_eq!(c.history.len(), 1);
+        assert_eq!(
+            (c.history[0].text.as_str(), &c.history[0].suggestion),
+            ("rename", &Some(first))
+        );
+        assert!(c.edited_at.is_some());
+
+        c.edit("rename".to_string());
+        assert!(c.suggestion.is_none());
+        assert_eq!(c.history.len(), 2);
+    }
+
+    #[test]
+    fn leading_tags_become_labels() {
+        let (severity, category, rest) = parse_labels("  [Blocker] [SECURITY] leaks the token");
+        assert_eq!(severity, Some(Severity::Blocker));
+        assert_eq!(category, Some(Category::Security));
+        assert_eq!(rest, "leaks the token");
+
+        // Unknown tags end the labels and stay in the text
+        let (severity, category, rest) = parse_labels("[nit][WIP] [style] spacing");
+        assert_eq!((severity, category), (Some(Severity::Nit), None));
+        assert_eq!(rest, "[WIP] [style] spacing");
+
+        assert_eq!(Category::parse("Performance"), Some(Category::Perf));
+        assert_eq!(Category::parse("test"), Some(Category::Tests));
+        assert_eq!(Category::parse("docs"), None);
+    }
+
+    #[test]
+    fn filters_keep_matching_threads_whole() {
+        let mut blocker = comment("blocker");
+        blocker.severity = Some(Severity::Blocker);
+        blocker.category = Some(Category::Security);
+        let reply = blocker.reply("unlabelled reply".to_string());
+        let mut nit = comment("nit");
+        nit.severity = Some(Severity::Nit);
+        nit.category = Some(Category::Style);
+        let unlabelled = comment("unlabelled");
+        let mut pending = blocker.clone();
+        pending.id = Uuid::new_v4();
+        pending.text = "pending".to_string();
+        pending.pending = true;
+        let comments = vec![blocker, reply, nit, unlabelled, pending];
+
+        let all = CommentFilter::default();
+        assert_eq!(
+            texts(&all.apply(&comments)),
+            ["blocker", "unlabelled reply", "nit", "unlabelled"]
+        );
+
+        let issues = CommentFilter {
+            min_severity: Some(Severity::Issue),
+            categories: Vec::new(),
+        };
+        assert_eq!(
+            texts(&issues.apply(&comments)),
+            ["blocker", "unlabelled reply"]
+        );
+
+        let style = CommentFilter {
+            min_severity: Some(Severity::Nit),
+            categories: vec![Category::Style, Category::Perf],
+        };
+        assert_eq!(texts(&style.apply(&comments)), ["nit"]);
+    }
+
+    #[test]
+    fn legacy_comments_get_stable_ids() {
+        let path = std::env::temp_dir().join(format!("vr-legacy-{}.json", Uuid::new_v4()));
+        fs::write(
+            &path,
+            r#"{"comments":[{"file_path":"a.rs","line_number":1,"text":"t","timestamp":"2024-01-01T00:00:00Z","context":[]}]}"#,
+        )
+        .unwrap();
+        let first = load_comments_from(&path).unwrap();
+        let second = load_comments_from(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!first[0].id.is_nil());
+        assert_eq!(first[0].id, second[0].id);
     }
 }
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings from the user's config file; every key is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub hooks: Hooks,
+}
+
+/// Commands run when comments are saved or vr exits
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    pub on_save: Option<String>, // After :w, :wq and :q write the output file
+    pub on_quit: Option<String>, // Once vr has exited
+    pub stdin: bool,             // Pipe the rendered comments to the command
+}
+
+impl Hooks {
+    /// These hooks with the commands given on the command line in place of
+    /// the configured ones
+    pub fn overridden_by(mut self, on_save: Option<String>, on_quit: Option<String>) -> Self {
+        if on_save.is_some() {
+            self.on_save = on_save;
+        }
+        if on_quit.is_some() {
+            self.on_quit = on_quit;
+        }
+        self
+    }
+}
+
+impl Config {
+    /// Load `$XDG_CONFIG_HOME/vr/config.toml` (`~/.config/vr/config.toml`),
+    /// or the defaults if there is none
+    pub fn load() -> Result<Self> {
+        let Some(path) = path().filter(|p| p.exists()) else {
+            return Ok(Self::default());
+        };
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+    }
+}
+
+/// The user-level config file, outside any repository
+fn path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("vr").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_hooks_win_over_the_config() {
+        let config: Config = toml::from_str(
+            "[hooks]\non_save = \"notify-send saved\"\non_quit = \"gh-post\"\nstdin = true\n",
+        )
+        .unwrap();
+        let hooks = config
+            .hooks
+            .overridden_by(Some("./post-review".into()), None);
+        assert_eq!(hooks.on_save.as_deref(), Some("./post-review"));
+        assert_eq!(hooks.on_quit.as_deref(), Some("gh-post"));
+        assert!(hooks.stdin);
+
+        assert!(toml::from_str::<Config>("[hooks]\non_exit = \"x\"\n").is_err());
+    }
+}
@@ -17,6 +17,21 @@ pub struct DiffLine {
     pub old_line_no: Option<usize>,
     pub new_line_no: Option<usize>,
     pub file_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>, // Commit of the range-diff pair the line is in
+}
+
+/// File path of the commit message ahead of the first file in `git show`,
+/// `git format-patch` or `hg export` output, as Gerrit names it
+pub const COMMIT_MSG: &str = "/COMMIT_MSG";
+
+/// Where parse_diff is in the commit ahead of the first file
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Preamble {
+    None,    // Not a commit, or past it
+    Header,  // "commit", "Author:", "Date:"; "# User" for hg
+    Message, // Numbered from 1, so comments can go on it
+    Trailer, // format-patch's diffstat after "---"
 }
 
 /// Parse unified diff format into structured DiffLine objects
@@ -25,19 +40,87 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
     let mut current_file = String::new();
     let mut old_line = 0;
     let mut new_line = 0;
+    let mut in_hunk = false;
+
+    let first = diff_text.lines().next().unwrap_or("");
+    let hg = first.starts_with("# HG changeset patch");
+    let mut preamble = if hg || first.starts_with("commit ") || first.starts_with("From ") {
+        Preamble::Header
+    } else {
+        Preamble::None
+    };
+    let mut message_line = 0;
+    let mut blank_lines = 0; // Held back until more of the message follows
 
     for line in diff_text.lines() {
-        if line.starts_with("diff --git") {
-            // Extract file path from "diff --git a/path b/path"
-            if let Some(path) = line.split_whitespace().nth(2) {
-                current_file = path.trim_start_matches("a/").to_string();
+        if preamble != Preamble::None && !line.starts_with("diff ") {
+            // hg has no blank line between its header and the message
+            if preamble == Preamble::Header && hg && !line.starts_with('#') {
+                preamble = Preamble::Message;
+            }
+            let header = |content: &str| DiffLine {
+                content: content.to_string(),
+                line_type: LineType::FileHeader,
+                old_line_no: None,
+                new_line_no: None,
+                file_path: COMMIT_MSG.to_string(),
+                commit: None,
+            };
+            match preamble {
+                Preamble::Header if line.is_empty() => preamble = Preamble::Message,
+                Preamble::Message if line == "---" => {
+                    preamble = Preamble::Trailer;
+                    lines.push(header(line));
+                }
+                Preamble::Message => {
+                    // git show indents the message by four spaces
+                    let text = line.strip_prefix("    ").unwrap_or(line);
+                    if text.trim().is_empty() {
+                        blank_lines += 1;
+                        continue;
+                    }
+                    for text in std::iter::repeat_n("", blank_lines).chain([text]) {
+                        message_line += 1;
+                        lines.push(DiffLine {
+                            content: format!(" {}", text),
+                            line_type: LineType::Context,
+                            old_line_no: Some(message_line),
+                            new_line_no: Some(message_line),
+                            file_path: COMMIT_MSG.to_string(),
+                            commit: None,
+                        });
+                    }
+                    blank_lines = 0;
+                }
+                _ if !line.is_empty() => lines.push(header(line)),
+                _ => {}
+            }
+            continue;
+        }
+
+        if line.starts_with("diff ") {
+            preamble = Preamble::None;
+            // Extract the new path from "diff --git a/path b/path", which
+            // differ for a rename, or from the last argument of
+            // "diff -r rev path" (mercurial)
+            let path = if line.starts_with("diff --git") {
+                line.split_whitespace()
+                    .nth(3)
+                    .map(|p| p.strip_prefix("b/").unwrap_or(p))
+            } else {
+                line.split_whitespace().last()
+            };
+            if let Some(path) = path {
+                current_file = path.to_string();
             }
+            in_hunk = false;
             lines.push(DiffLine {
                 content: line.to_string(),
                 line_type: LineType::FileHeader,
                 old_line_no: None,
                 new_line_no: None,
                 file_path: current_file.clone(),
+                commit: None,
             });
         } else if line.starts_with("---") || line.starts_with("+++") {
             // File header lines
@@ -47,6 +130,7 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
                 old_line_no: None,
                 new_line_no: None,
                 file_path: current_file.clone(),
+                commit: None,
             });
         } else if line.starts_with("@@") {
             // Hunk header: @@ -old_start,old_count +new_start,new_count @@
@@ -54,12 +138,14 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
                 old_line = hunk_info.0;
                 new_line = hunk_info.1;
             }
+            in_hunk = true;
             lines.push(DiffLine {
                 content: line.to_string(),
                 line_type: LineType::HunkHeader,
                 old_line_no: None,
                 new_line_no: None,
                 file_path: current_file.clone(),
+                commit: None,
             });
         } else if line.starts_with('+') && !line.starts_with("+++") {
             // Added line
@@ -69,6 +155,7 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
                 old_line_no: None,
                 new_line_no: Some(new_line),
                 file_path: current_file.clone(),
+                commit: None,
             });
             new_line += 1;
         } else if line.starts_with('-') && !line.starts_with("---") {
@@ -79,8 +166,19 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
                 old_line_no: Some(old_line),
                 new_line_no: None,
                 file_path: current_file.clone(),
+                commit: None,
             });
             old_line += 1;
+        } else if !in_hunk && !line.is_empty() {
+            // Metadata before the first hunk ("index ...", "new file mode ...")
+            lines.push(DiffLine {
+                content: line.to_string(),
+                line_type: LineType::FileHeader,
+                old_line_no: None,
+                new_line_no: None,
+                file_path: current_file.clone(),
+                commit: None,
+            });
         } else if line.starts_with(' ') || (!line.starts_with("diff") && !line.is_empty()) {
             // Context line
             lines.push(DiffLine {
@@ -89,6 +187,7 @@ pub fn parse_diff(diff_text: &str) -> Result<Vec<DiffLine>> {
                 old_line_no: Some(old_line),
                 new_line_no: Some(new_line),
                 file_path: current_file.clone(),
+                commit: None,
             });
             old_line += 1;
             new_line += 1;
@@ -115,3 +214,354 @@ fn parse_hunk_header(line: &str) -> Option<(usize, usize)> {
 
     Some((old_start, new_start))
 }
+
+/// A single hunk's extent within the parsed diff lines
+#[derive(Debug, Clone)]
+pub struct HunkSpan {
+    pub start: usize, // Index of the hunk header
+    pub end: usize,   // Exclusive
+    pub added: usize,
+    pub removed: usize,
+    pub hash: u64,
+}
+
+/// A single file's extent within the parsed diff lines
+#[derive(Debug, Clone)]
+pub struct FileSpan {
+    pub path: String,
+    pub start: usize, // Index of the first file header line
+    pub end: usize,   // Exclusive
+    pub hunks: Vec<HunkSpan>,
+    pub added: usize,
+    pub removed: usize,
+    pub hash: u64,
+}
+
+impl FileSpan {
+    pub fn contains(&self, index: usize) -> bool {
+        (self.start..self.end).contains(&index)
+    }
+
+    /// Find the hunk containing the given line index
+    pub fn hunk_at(&self, index: usize) -> Option<&HunkSpan> {
+        self.hunks
+            .iter()
+            .find(|h| (h.start..h.end).contains(&index))
+    }
+}
+
+/// Group parsed diff lines into files and hunks
+///
+/// A new file starts at every file header that doesn't directly follow
+/// another file header of the same file, so "diff --git", "---" and "+++"
+/// stay together. A commit's header and message come first, as their own
+/// file under COMMIT_MSG.
+pub fn index_files(lines: &[DiffLine]) -> Vec<FileSpan> {
+    let mut files: Vec<FileSpan> = Vec::new();
+
+    for (idx, line) in lines.iter().enumerate() {
+        let starts_file = line.line_type == LineType::FileHeader
+            && (idx == 0
+                || lines[idx - 1].line_type != LineType::FileHeader
+                || lines[idx - 1].file_path != line.file_path);
+
+        if starts_file || files.is_empty() {
+            files.push(FileSpan {
+                path: line.file_path.clone(),
+                start: idx,
+                end: idx,
+                hunks: Vec::new(),
+                added: 0,
+                removed: 0,
+                hash: 0,
+            });
+        }
+
+        let file = files.last_mut().expect("a file span was just pushed");
+        file.end = idx + 1;
+
+        match line.line_type {
+            LineType::HunkHeader => file.hunks.push(HunkSpan {
+                start: idx,
+                end: idx + 1,
+                added: 0,
+                removed: 0,
+                hash: 0,
+            }),
+            LineType::FileHeader => {}
+            _ => {
+                if let Some(hunk) = file.hunks.last_mut() {
+                    hunk.end = idx + 1;
+                    match line.line_type {
+                        LineType::Added => hunk.added += 1,
+                        LineType::Removed => hunk.removed += 1,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    for file in &mut files {
+        file.added = file.hunks.iter().map(|h| h.added).sum();
+        file.removed = file.hunks.iter().map(|h| h.removed).sum();
+        file.hash = content_hash(
+            std::iter::once(file.path.as_str()).chain(
+                lines[file.start..file.end]
+                    .iter()
+                    .map(|l| l.content.as_str()),
+            ),
+        );
+        for hunk in &mut file.hunks {
+            // Skip the hunk header so hunks keep their hash when earlier
+            // hunks in the file grow or shrink
+            hunk.hash = content_hash(
+                std::iter::once(file.path.as_str()).chain(
+                    lines[hunk.start + 1..hunk.end]
+                        .iter()
+                        .map(|l| l.content.as_str()),
+                ),
+            );
+        }
+    }
+
+    files
+}
+
+/// Stable FNV-1a hash over a sequence of lines
+///
+/// Used to detect when a file or hunk changed between review sessions, so it
+/// must not depend on the Rust version or process like `DefaultHasher` does.
+pub fn content_hash<'a>(lines: impl IntoIterator<Item = &'a str>) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET;
+    for line in lines {
+        for byte in line.bytes().chain(std::iter::once(b'\n')) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+/// Where a new-side line of `file` in the `old` diff is in the `new` diff,
+/// for two diffs taken against the same base
+///
+/// Lines outside the changes keep their place relative to the base, and an
+/// added line moves with the first line above it that isn't added.
+pub fn map_line(old: &[DiffLine], new: &[DiffLine], file: &str, line: usize) -> usize {
+    // How far into a run of added lines this line is
+    let lines: Vec<&DiffLine> = old.iter().filter(|l| l.file_path == file).collect();
+    let added_run = match lines.iter().position(|l| l.new_line_no == Some(line)) {
+        Some(idx) => lines[..=idx]
+            .iter()
+            .rev()
+            .take_while(|l| matches!(l.line_type, LineType::Added | LineType::Removed))
+            .filter(|l| l.line_type == LineType::Added)
+            .count(),
+        None => 0,
+    };
+
+    let above = line.saturating_sub(added_run);
+    let base = above as isize - shift_before(old, file, above, true);
+    let mapped = base + shift_before(new, file, base.max(0) as usize, false);
+    mapped.max(0) as usize + added_run
+}
+
+/// Lines added minus lines removed in `file` ahead of the given line, which
+/// is a new-side line number if `new_side` and a base line number otherwise
+fn shift_before(diff: &[DiffLine], file: &str, line: usize, new_side: bool) -> isize {
+    let (mut old, mut new, mut shift) = (0, 0, 0);
+    for l in diff.iter().filter(|l| l.file_path == file) {
+        match l.line_type {
+            LineType::HunkHeader => {
+                if let Some((o, n)) = parse_hunk_header(&l.content) {
+                    (old, new) = (o, n);
+                }
+            }
+            LineType::Context => {
+                old += 1;
+                new += 1;
+            }
+            // Added lines go in before the next base line, removed ones
+            // before the next new line
+            LineType::Added => {
+                if (new_side && new < line) || (!new_side && old <= line) {
+                    shift += 1;
+                }
+                new += 1;
+            }
+            LineType::Removed => {
+                if (new_side && new <= line) || (!new_side && old < line) {
+                    shift -= 1;
+                }
+                old += 1;
+            }
+            LineType::FileHeader => {}
+        }
+    }
+    shift
+}
+
+/// New-side line number of the place in `file` where the new-side lines
+/// `context` (without diff markers) appear, ending on the line wanted;
+/// the match nearest `near` wins
+pub fn find_line(diff: &[DiffLine], file: &str, near: usize, context: &[&str]) -> Option<usize> {
+    if context.is_empty() {
+        return None;
+    }
+    let lines: Vec<&DiffLine> = diff
+        .iter()
+        .filter(|l| l.file_path == file && l.new_line_no.is_some())
+        .collect();
+
+    (0..lines.len())
+        .filter(|&end| {
+            end + 1 >= context.len()
+                && lines[end + 1 - context.len()..=end]
+                    .iter()
+                    .zip(context)
+                    .all(|(l, text)| l.content.get(1..).unwrap_or("") == *text)
+        })
+        .filter_map(|end| lines[end].new_line_no)
+        .min_by_key(|&line_no| line_no.abs_diff(near))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIT_SHOW: &str = "\
+commit 0123456789abcdef0123456789abcdef01234567
+Author: A U Thor <author@example.com>
+Date:   Mon Oct 19 04:52:38 2026 +0000
+
+    Subject line
+    
+    Body line
+
+diff --git a/f.txt b/f.txt
+index 7898192..6178079 100644
+--- a/f.txt
++++ b/f.txt
+@@ -1 +1 @@
+-a
++b
+";
+
+    #[test]
+    fn commit_message_is_its_own_file() {
+        let lines = parse_diff(GIT_SHOW).unwrap();
+        let files = index_files(&lines);
+
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, [COMMIT_MSG, "f.txt"]);
+        assert!(files[0].hunks.is_empty());
+        assert_eq!((files[1].added, files[1].removed), (1, 1));
+
+        let message: Vec<(Option<usize>, &str)> = lines[files[0].start..files[0].end]
+            .iter()
+            .filter(|l| l.line_type == LineType::Context)
+            .map(|l| (l.new_line_no, l.content.as_str()))
+            .collect();
+        assert_eq!(
+            message,
+            [
+                (Some(1), " Subject line"),
+                (Some(2), " "),
+                (Some(3), " Body line")
+            ]
+        );
+    }
+
+    #[test]
+    fn hg_export_message() {
+        let export = "\
+# HG changeset patch
+# User A U Thor <author@example.com>
+# Node ID 0123456789abcdef0123456789abcdef01234567
+Subject line
+diff -r 89abcdef0123 -r 0123456789ab f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -1 +1 @@
+-a
++b
+";
+        let lines = parse_diff(export).unwrap();
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.file_path == COMMIT_MSG)
+                .map(|l| l.line_type.clone())
+                .collect::<Vec<_>>(),
+            [
+                LineType::FileHeader,
+                LineType::FileHeader,
+                LineType::FileHeader,
+                LineType::Context
+            ]
+        );
+        let paths: Vec<String> = index_files(&lines).into_iter().map(|f| f.path).collect();
+        assert_eq!(paths, [COMMIT_MSG, "f.txt"]);
+    }
+
+    #[test]
+    fn plain_diffs_have_no_commit_message() {
+        let lines = parse_diff("--- a/f.txt\n+++ b/f.txt\n@@ -1 +1 @@\n-a\n+b\n").unwrap();
+        assert!(lines.iter().all(|l| l.file_path != COMMIT_MSG));
+        assert_eq!(index_files(&lines).len(), 1);
+    }
+
+    fn fixture() -> Vec<DiffLine> {
+        parse_diff(include_str!("../tests/fixtures/review.diff")).unwrap()
+    }
+
+    #[test]
+    fn line_numbers_and_spans() {
+        let lines = fixture();
+        let files = index_files(&lines);
+        let summary: Vec<(&str, usize, usize, usize)> = files
+            .iter()
+            .map(|f| (f.path.as_str(), f.hunks.len(), f.added, f.removed))
+            .collect();
+        assert_eq!(summary, [("src/lib.rs", 2, 4, 2), ("src/main.rs", 1, 1, 0)]);
+
+        let y = lines
+            .iter()
+            .find(|l| l.content == "+fn y() -> u8 { 2 }")
+            .unwrap();
+        assert_eq!((y.old_line_no, y.new_line_no), (None, Some(23)));
+        let x = lines.iter().find(|l| l.content == " fn x() {}").unwrap();
+        assert_eq!((x.old_line_no, x.new_line_no), (Some(20), Some(22)));
+    }
+
+    #[test]
+    fn map_line_follows_the_changes() {
+        let lines = fixture();
+        // Against the base, lines after the first hunk move up by two
+        assert_eq!(map_line(&lines, &[], "src/lib.rs", 22), 20);
+        assert_eq!(map_line(&lines, &[], "src/lib.rs", 30), 28);
+        // Added lines move with the line above them
+        assert_eq!(map_line(&lines, &[], "src/lib.rs", 3), 3);
+        assert_eq!(map_line(&[], &lines, "src/lib.rs", 20), 22);
+        assert_eq!(map_line(&lines, &lines, "src/main.rs", 2), 2);
+        // Other files are left alone
+        assert_eq!(map_line(&lines, &[], "README.md", 7), 7);
+    }
+
+    #[test]
+    fn find_line_matches_context_ending_on_the_line() {
+        let lines = fixture();
+        assert_eq!(find_line(&lines, "src/lib.rs", 1, &["fn c() {}"]), Some(5));
+        assert_eq!(find_line(&lines, "src/lib.rs", 1, &["    1", "}"]), Some(4));
+        assert_eq!(
+            find_line(&lines, "src/lib.rs", 30, &["fn z() {}"]),
+            Some(24)
+        );
+        assert_eq!(find_line(&lines, "src/lib.rs", 1, &["missing"]), None);
+        assert_eq!(find_line(&lines, "src/lib.rs", 1, &[]), None);
+    }
+}
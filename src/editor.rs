@@ -0,0 +1,337 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Whether keys typed into the comment insert or run vim-style edits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorMode {
+    Insert,
+    Normal,
+}
+
+/// Multi-line text editor for the comment being written
+///
+/// The cursor is a byte offset that always sits on a grapheme cluster
+/// boundary, so combining characters move and delete together with their
+/// base character. Vertical movement keeps the display column, which
+/// accounts for double-width characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentEditor {
+    text: String,
+    cursor: usize,
+    pub mode: EditorMode,
+    pub pending: Option<char>, // Operator waiting for its repeat, e.g. the first d of dd
+}
+
+impl CommentEditor {
+    /// Start editing the given text with the cursor at its end
+    pub fn new(text: String) -> Self {
+        let cursor = text.len();
+        Self {
+            text,
+            cursor,
+            mode: EditorMode::Insert,
+            pending: None,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        // Pasted text from terminals often uses CRLF line endings
+        let s = s.replace("\r\n", "\n").replace('\r', "\n");
+        self.text.insert_str(self.cursor, &s);
+        self.cursor += s.len();
+    }
+
+    /// Delete the grapheme before the cursor
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete the grapheme under the cursor
+    pub fn delete(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    pub fn move_left(&mut self) {
+        if self.cursor > self.line_start() {
+            self.cursor = self.prev_boundary(self.cursor);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.cursor < self.line_end() {
+            self.cursor = self.next_boundary(self.cursor);
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        let start = self.line_start();
+        if start == 0 {
+            return;
+        }
+        let column = self.column();
+        let prev_start = self.text[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        self.cursor = self.offset_at_column(prev_start, column);
+    }
+
+    pub fn move_down(&mut self) {
+        let end = self.line_end();
+        if end == self.text.len() {
+            return;
+        }
+        let column = self.column();
+        self.cursor = self.offset_at_column(end + 1, column);
+    }
+
+    pub fn move_line_start(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    pub fn move_line_end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    /// Move to the start of the next word (vim's w)
+    pub fn move_word_forward(&mut self) {
+        let mut pos = self.cursor;
+        let class = self.class_at(pos);
+        while pos < self.text.len() && class.is_some() && self.class_at(pos) == class {
+            pos = self.next_boundary(pos);
+        }
+        while pos < self.text.len() && self.class_at(pos).is_none() {
+            pos = self.next_boundary(pos);
+        }
+        self.cursor = pos;
+    }
+
+    /// Move to the start of the previous word (vim's b)
+    pub fn move_word_backward(&mut self) {
+        self.cursor = self.word_start_before(self.cursor);
+    }
+
+    /// Delete the word before the cursor (Ctrl-w)
+    pub fn delete_word_before(&mut self) {
+        let start = self.word_start_before(self.cursor).max(self.line_start());
+        // At the start of a line, join it with the previous one instead
+        let start = if start == self.cursor {
+            self.prev_boundary(self.cursor)
+        } else {
+            start
+        };
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete from the start of the line to the cursor (Ctrl-u)
+    pub fn delete_to_line_start(&mut self) {
+        let start = self.line_start();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete from the cursor to the end of the line (D)
+    pub fn delete_to_line_end(&mut self) {
+        let end = self.line_end();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Delete the whole cursor line (dd)
+    pub fn delete_line(&mut self) {
+        let start = self.line_start();
+        let end = self.line_end();
+        let (start, end) = if end < self.text.len() {
+            (start, end + 1)
+        } else {
+            (start.saturating_sub(1), end)
+        };
+        self.text.replace_range(start..end, "");
+        self.cursor = start.min(self.text.len());
+        self.cursor = self.line_start();
+    }
+
+    /// Open a new empty line below the cursor line (o)
+    pub fn open_line_below(&mut self) {
+        self.cursor = self.line_end();
+        self.insert_char('\n');
+    }
+
+    /// Open a new empty line above the cursor line (O)
+    pub fn open_line_above(&mut self) {
+        self.cursor = self.line_start();
+        self.insert_char('\n');
+        self.cursor -= 1;
+    }
+
+    /// Switch to the normal sub-mode; like vim, the cursor steps back onto
+    /// the last character it typed
+    pub fn enter_normal_mode(&mut self) {
+        self.mode = EditorMode::Normal;
+        self.pending = None;
+        self.move_left();
+    }
+
+    pub fn enter_insert_mode(&mut self) {
+        self.mode = EditorMode::Insert;
+        self.pending = None;
+    }
+
+    /// Byte offset of the start of the cursor line
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Byte offset of the end of the cursor line, before its newline
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    /// Display column of the cursor within its line
+    fn column(&self) -> usize {
+        self.text[self.line_start()..self.cursor].width()
+    }
+
+    /// Offset of the grapheme at the given display column of the line
+    /// starting at `line_start`, clamped to the end of that line
+    fn offset_at_column(&self, line_start: usize, column: usize) -> usize {
+        let line_end = self.text[line_start..]
+            .find('\n')
+            .map_or(self.text.len(), |i| line_start + i);
+
+        let mut width = 0;
+        for (i, g) in self.text[line_start..line_end].grapheme_indices(true) {
+            width += g.width();
+            if width > column {
+                return line_start + i;
+            }
+        }
+        line_end
+    }
+
+    fn prev_boundary(&self, pos: usize) -> usize {
+        self.text[..pos]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, pos: usize) -> usize {
+        pos + self.text[pos..].graphemes(true).next().map_or(0, str::len)
+    }
+
+    /// Character class of the grapheme at `pos` for word motions: `None` for
+    /// whitespace, `Some(true)` for word characters, `Some(false)` otherwise
+    fn class_at(&self, pos: usize) -> Option<bool> {
+        let c = self.text[pos..].chars().next()?;
+        if c.is_whitespace() {
+            None
+        } else {
+            Some(c.is_alphanumeric() || c == '_')
+        }
+    }
+
+    fn word_start_before(&self, pos: usize) -> usize {
+        let mut pos = pos;
+        while pos > 0 && self.class_at(self.prev_boundary(pos)).is_none() {
+            pos = self.prev_boundary(pos);
+        }
+        if pos == 0 {
+            return 0;
+        }
+        let class = self.class_at(self.prev_boundary(pos));
+        while pos > 0 && self.class_at(self.prev_boundary(pos)) == class {
+            pos = self.prev_boundary(pos);
+        }
+        pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphemes_move_and_delete_together() {
+        let mut editor = CommentEditor::new("ae\u{301}".to_string());
+        editor.backspace();
+        assert_eq!(editor.text(), "a");
+
+        editor.insert_str("b\r\nc");
+        assert_eq!(editor.text(), "ab\nc");
+        editor.move_left();
+        assert_eq!(editor.cursor(), 3); // Stops at the start of the line
+    }
+
+    #[test]
+    fn vertical_moves_keep_the_display_column() {
+        // The wide character takes two columns
+        let mut editor = CommentEditor::new("漢x\nabcd\nz".to_string());
+        editor.move_up();
+        editor.move_up();
+        editor.move_line_end();
+        assert_eq!(editor.cursor(), "漢x".len());
+        editor.move_down();
+        assert_eq!(editor.cursor(), "漢x\nabc".len());
+        editor.move_down();
+        assert_eq!(editor.cursor(), editor.text().len());
+    }
+
+    #[test]
+    fn word_motions_and_deletes() {
+        let mut editor = CommentEditor::new("fix foo.bar now".to_string());
+        editor.move_line_start();
+        editor.move_word_forward();
+        assert_eq!(editor.cursor(), 4);
+        editor.move_word_forward();
+        assert_eq!(&editor.text()[editor.cursor()..], ".bar now");
+
+        editor.move_line_end();
+        editor.delete_word_before();
+        assert_eq!(editor.text(), "fix foo.bar ");
+        editor.move_word_backward();
+        editor.delete_to_line_end();
+        assert_eq!(editor.text(), "fix foo.");
+        editor.delete_to_line_start();
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn line_edits() {
+        let mut editor = CommentEditor::new("one\ntwo\nthree".to_string());
+        editor.move_up();
+        editor.delete_line();
+        assert_eq!(editor.text(), "one\nthree");
+        assert_eq!(editor.cursor(), 4);
+
+        editor.open_line_above();
+        editor.insert_str("2");
+        editor.open_line_below();
+        editor.insert_str("2.5");
+        assert_eq!(editor.text(), "one\n2\n2.5\nthree");
+
+        editor.move_down();
+        editor.delete_line();
+        assert_eq!(editor.text(), "one\n2\n2.5");
+        assert_eq!(editor.cursor(), "one\n2\n".len());
+    }
+}
@@ -0,0 +1,164 @@
+use super::{hosted_body, summary, threads};
+use crate::comment::{Comment, CommentStatus};
+use crate::diff::{DiffLine, LineType, COMMIT_MSG};
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+/// `ReviewInput` for Gerrit's set-review endpoint
+/// (POST /changes/{change-id}/revisions/{revision-id}/review)
+///
+/// Comments are keyed by file, and comments on the commit message are
+/// moved below the header Gerrit adds to it (see [`commit_msg_header`]),
+/// using the commit in `diff`. Open threads are posted
+/// unresolved. Threads with a suggested change go under `robot_comments`
+/// with it as a fix suggestion, since Gerrit before 3.10 only takes fix
+/// suggestions on robot comments.
+pub fn render(comments: &[Comment], diff: &[DiffLine]) -> Result<String> {
+    let header = commit_msg_header(diff);
+    let mut by_file: Map<String, Value> = Map::new();
+    let mut robot_by_file: Map<String, Value> = Map::new();
+    let run_id = Uuid::new_v4().to_string();
+
+    for thread in threads(comments) {
+        let path = thread.file_path.as_str();
+        let offset = if path == COMMIT_MSG { header } else { 0 };
+
+        let mut input = json!({
+            "line": thread.line_number + offset,
+            "message": hosted_body(thread, comments, None),
+            "unresolved": thread.status == CommentStatus::Open,
+        });
+
+        let mut list = &mut by_file;
+        if let Some(s) = &thread.suggestion {
+            // The range covers the replaced lines up to the end of the last
+            // one; `line` must then be its last line
+            let range = json!({
+                "start_line": thread.line_number + offset,
+                "start_character": 0,
+                "end_line": s.end_line + offset,
+                "end_character": s.original.last().map_or(0, |l| l.chars().count()),
+            });
+            input["line"] = json!(s.end_line + offset);
+            input["range"] = range.clone();
+            input["fix_suggestions"] = json!([{
+                "description": "Suggested change",
+                "replacements": [{
+                    "path": path,
+                    "range": range,
+                    "replacement": s.replacement,
+                }],
+            }]);
+            input["robot_id"] = json!("vr");
+            input["robot_run_id"] = json!(run_id);
+            list = &mut robot_by_file;
+        }
+
+        list.entry(path)
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .expect("comment lists are arrays")
+            .push(input);
+    }
+
+    let mut review = json!({
+        "message": summary(comments),
+        "comments": by_file,
+    });
+    if !robot_by_file.is_empty() {
+        review["robot_comments"] = Value::Object(robot_by_file);
+    }
+    Ok(serde_json::to_string_pretty(&review)? + "\n")
+}
+
+/// Lines Gerrit shows ahead of the message in /COMMIT_MSG: a Parent line,
+/// or a Merge Of line per parent, then Author, AuthorDate, Commit,
+/// CommitDate and a blank line
+///
+/// The parents are counted from the commit's `Merge:` line (git) or
+/// `# Parent` lines (hg). Neither `git show` nor `hg export` tells whether
+/// the committer and commit time match the author's, in which case Gerrit
+/// leaves out Commit and CommitDate; that's rare for an uploaded change,
+/// which has been committed after it was written, so they are assumed.
+fn commit_msg_header(diff: &[DiffLine]) -> usize {
+    let header: Vec<&str> = diff
+        .iter()
+        .filter(|l| l.file_path == COMMIT_MSG && l.line_type == LineType::FileHeader)
+        .map(|l| l.content.as_str())
+        .collect();
+    let hg_parents = header.iter().filter(|l| l.starts_with("# Parent")).count();
+    let parents = header
+        .iter()
+        .find_map(|l| l.strip_prefix("Merge:"))
+        .map_or(hg_parents.max(1), |p| p.split_whitespace().count());
+    parents + 5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::fixture;
+
+    #[test]
+    fn suggestions_become_robot_comments_with_fix_suggestions() {
+        let mut resolved = Comment::new("src/main.rs".into(), 2, "Done".into(), Vec::new());
+        resolved.status = CommentStatus::Resolved;
+        let review: Value = serde_json::from_str(
+            &render(&[fixture::suggestion(), resolved], &fixture::diff()).unwrap(),
+        )
+        .unwrap();
+
+        assert!(review["comments"].get("src/lib.rs").is_none());
+        let input = &review["robot_comments"]["src/lib.rs"][0];
+        let range = json!({
+            "start_line": 2,
+            "start_character": 0,
+            "end_line": 4,
+            "end_character": 1,
+        });
+        assert_eq!(input["robot_id"], "vr");
+        assert!(input["robot_run_id"].is_string());
+        assert_eq!(input["line"], 4);
+        assert_eq!(input["range"], range);
+        assert_eq!(input["unresolved"], true);
+        assert_eq!(
+            input["fix_suggestions"][0]["replacements"][0],
+            json!({ "path": "src/lib.rs", "range": range, "replacement": "fn b() -> u8 { 1 }" })
+        );
+
+        let resolved = &review["comments"]["src/main.rs"][0];
+        assert_eq!(
+            (&resolved["line"], &resolved["unresolved"]),
+            (&json!(2), &json!(false))
+        );
+        assert!(resolved.get("range").is_none());
+        assert!(resolved.get("robot_id").is_none());
+    }
+
+    #[test]
+    fn commit_message_lines_skip_gerrits_header() {
+        let comments = [Comment::new(
+            COMMIT_MSG.to_string(),
+            1,
+            "Say why".to_string(),
+            Vec::new(),
+        )];
+        let line = |diff: &str| {
+            let diff = crate::diff::parse_diff(diff).unwrap();
+            let review: Value = serde_json::from_str(&render(&comments, &diff).unwrap()).unwrap();
+            assert!(review.get("robot_comments").is_none());
+            review["comments"][COMMIT_MSG][0]["line"].clone()
+        };
+        let patch = "diff --git a/f b/f\n--- a/f\n+++ b/f\n@@ -1 +1 @@\n-a\n+b\n";
+
+        let commit = "commit 1111111\nAuthor: A <a@example.com>\nDate:   Mon Jan 1 00:00:00 2024 +0000\n\n    Subject\n\n";
+        assert_eq!(line(&format!("{}{}", commit, patch)), 7);
+
+        // A merge has a line per parent
+        let merge = "commit 1111111\nMerge: 2222222 3333333\nAuthor: A <a@example.com>\nDate:   Mon Jan 1 00:00:00 2024 +0000\n\n    Merge branch 'topic'\n\n";
+        assert_eq!(line(&format!("{}{}", merge, patch)), 8);
+        let hg_merge = "# HG changeset patch\n# User A <a@example.com>\n# Node ID 1111\n# Parent  2222\n# Parent  3333\nMerge topic\n\n";
+        assert_eq!(line(&format!("{}{}", hg_merge, patch)), 8);
+    }
+}
@@ -0,0 +1,225 @@
+use super::{hosted_body, summary, threads, ReviewInfo};
+use crate::comment::Comment;
+use crate::diff::{DiffLine, LineType, COMMIT_MSG};
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+/// How review comments are placed in the diff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Line,     // line/side/start_line, for multi-line comments
+    Position, // Lines below the file's first @@, for older GitHub Enterprise
+}
+
+/// Body for GitHub's "create a review for a pull request" endpoint
+/// (POST /repos/{owner}/{repo}/pulls/{pull_number}/reviews)
+///
+/// GitHub rejects the whole review if one comment is outside the diff, so
+/// comments on lines the current diff doesn't show, ranges that leave their
+/// hunk and comments on the commit message are listed in the review body
+/// instead.
+pub fn render(
+    comments: &[Comment],
+    review: &ReviewInfo,
+    diff: &[DiffLine],
+    anchor: Anchor,
+) -> Result<String> {
+    let mut inline = Vec::new();
+    let mut outside = Vec::new();
+
+    for thread in threads(comments) {
+        let body = hosted_body(thread, comments, Some("```suggestion"));
+        let end_line = thread
+            .suggestion
+            .as_ref()
+            .map_or(thread.line_number, |s| s.end_line);
+
+        let mut c = Map::new();
+        c.insert("path".into(), json!(thread.file_path));
+        match anchor {
+            Anchor::Line
+                if thread.file_path != COMMIT_MSG
+                    && (diff.is_empty() || in_one_hunk(diff, thread, end_line)) =>
+            {
+                // Multi-line comments are anchored at their last line
+                c.insert("line".into(), json!(end_line));
+                c.insert("side".into(), json!("RIGHT"));
+                if end_line > thread.line_number {
+                    c.insert("start_line".into(), json!(thread.line_number));
+                    c.insert("start_side".into(), json!("RIGHT"));
+                }
+            }
+            Anchor::Position => match position(diff, thread) {
+                Some(position) => {
+                    c.insert("position".into(), json!(position));
+                }
+                None => {
+                    outside.push((thread, body));
+                    continue;
+                }
+            },
+            Anchor::Line => {
+                outside.push((thread, body));
+                continue;
+            }
+        }
+        c.insert("body".into(), json!(body));
+        inline.push(Value::Object(c));
+    }
+
+    let mut body = summary(comments);
+    for (thread, text) in outside {
+        body.push_str(&format!(
+            "\n\n`{}:{}`\n\n{}",
+            thread.file_path, thread.line_number, text
+        ));
+    }
+
+    let mut payload = Map::new();
+    if let Some(sha) = &review.head_sha {
+        payload.insert("commit_id".into(), json!(sha));
+    }
+    payload.insert("body".into(), json!(body));
+    payload.insert("event".into(), json!("COMMENT"));
+    payload.insert("comments".into(), Value::Array(inline));
+
+    Ok(serde_json::to_string_pretty(&payload)? + "\n")
+}
+
+/// Whether a comment's lines, from its line to `end_line`, are all in one
+/// hunk of the diff
+fn in_one_hunk(diff: &[DiffLine], comment: &Comment, end_line: usize) -> bool {
+    let hunk = |line: usize| {
+        let index = diff.iter().position(|l| {
+            l.file_path == comment.file_path
+                && l.line_type != LineType::Removed
+                && l.new_line_no == Some(line)
+        })?;
+        diff[..index]
+            .iter()
+            .rposition(|l| l.line_type == LineType::HunkHeader)
+    };
+    hunk(comment.line_number).is_some_and(|start| hunk(end_line) == Some(start))
+}
+
+/// GitHub's diff position of a comment: the number of lines below the first
+/// `@@` header of its file, counting later hunk headers too
+fn position(diff: &[DiffLine], comment: &Comment) -> Option<usize> {
+    let first_hunk = diff
+        .iter()
+        .position(|l| l.file_path == comment.file_path && l.line_type == LineType::HunkHeader)?;
+
+    diff[first_hunk..]
+        .iter()
+        .take_while(|l| l.file_path == comment.file_path)
+        .position(|l| {
+            l.line_type != LineType::Removed && l.new_line_no == Some(comment.line_number)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Suggestion;
+    use crate::export::fixture;
+
+    fn review(anchor: Anchor) -> Value {
+        let comments = [
+            fixture::suggestion(),
+            Comment::new("src/lib.rs".into(), 23, "Why 2?".into(), Vec::new()),
+            Comment::new("src/main.rs".into(), 2, "Use the result".into(), Vec::new()),
+            Comment::new(
+                "src/lib.rs".into(),
+                10,
+                "Not in the diff".into(),
+                Vec::new(),
+            ),
+        ];
+        let review = ReviewInfo {
+            head_sha: Some("abc123".into()),
+            ..ReviewInfo::default()
+        };
+        let diff = fixture::diff();
+        serde_json::from_str(&render(&comments, &review, &diff, anchor).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn positions_count_lines_below_the_first_hunk() {
+        let review = review(Anchor::Position);
+        let positions: Vec<(&str, u64)> = review["comments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| (c["path"].as_str().unwrap(), c["position"].as_u64().unwrap()))
+            .collect();
+        // The second hunk's header counts too
+        assert_eq!(
+            positions,
+            [("src/lib.rs", 3), ("src/lib.rs", 10), ("src/main.rs", 2)]
+        );
+        assert!(review["body"]
+            .as_str()
+            .unwrap()
+            .contains("`src/lib.rs:10`\n\nNot in the diff"));
+    }
+
+    #[test]
+    fn ranges_leaving_their_hunk_go_in_the_body() {
+        let ranged = |line: usize, end_line: usize| {
+            let mut c = Comment::new("src/lib.rs".into(), line, "Range".into(), Vec::new());
+            c.suggestion = Some(Suggestion {
+                end_line,
+                original: Vec::new(),
+                replacement: String::new(),
+            });
+            c
+        };
+        // Across both hunks, and past the end of the second
+        let comments = [ranged(4, 23), ranged(23, 30)];
+        let review: Value = serde_json::from_str(
+            &render(
+                &comments,
+                &ReviewInfo::default(),
+                &fixture::diff(),
+                Anchor::Line,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(review["comments"], json!([]));
+        let body = review["body"].as_str().unwrap();
+        assert!(body.contains("`src/lib.rs:4`") && body.contains("`src/lib.rs:23`"));
+
+        // Without a diff to check against, the commit message still can't be
+        // commented inline
+        let message = [Comment::new(
+            COMMIT_MSG.into(),
+            1,
+            "Typo".into(),
+            Vec::new(),
+        )];
+        let review: Value = serde_json::from_str(
+            &render(&message, &ReviewInfo::default(), &[], Anchor::Line).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(review["comments"], json!([]));
+        assert!(review["body"].as_str().unwrap().contains("`/COMMIT_MSG:1`"));
+    }
+
+    #[test]
+    fn lines_carry_the_suggestion_range() {
+        let review = review(Anchor::Line);
+        assert_eq!(review["commit_id"], "abc123");
+        let first = &review["comments"][0];
+        assert_eq!(
+            (&first["start_line"], &first["line"], &first["side"]),
+            (&json!(2), &json!(4), &json!("RIGHT"))
+        );
+        assert_eq!(
+            first["body"],
+            "Inline it\n\n```suggestion\nfn b() -> u8 { 1 }\n```"
+        );
+        assert!(review["comments"][1].get("start_line").is_none());
+        assert_eq!(review["comments"].as_array().unwrap().len(), 3);
+    }
+}
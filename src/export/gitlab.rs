@@ -0,0 +1,158 @@
+use super::{hosted_body, threads, ReviewInfo};
+use crate::comment::Comment;
+use crate::diff::{DiffLine, LineType, COMMIT_MSG};
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Bodies for GitLab's "create new merge request thread" endpoint
+/// (POST /projects/{id}/merge_requests/{iid}/discussions), one per thread
+///
+/// Context lines carry both their old and new line numbers, as GitLab
+/// requires, and renamed files their path from before the rename. Comments on lines the current diff doesn't show become
+/// general threads that start with their location.
+pub fn render(comments: &[Comment], review: &ReviewInfo, diff: &[DiffLine]) -> Result<String> {
+    let discussions: Vec<Value> = threads(comments)
+        .into_iter()
+        .map(|thread| {
+            // GitLab's suggestion fence counts the extra lines it replaces
+            let fence = match &thread.suggestion {
+                Some(s) if s.end_line > thread.line_number => {
+                    format!("```suggestion:-0+{}", s.end_line - thread.line_number)
+                }
+                _ => "```suggestion".to_string(),
+            };
+            let body = hosted_body(thread, comments, Some(&fence));
+
+            match diff_line(diff, thread) {
+                Some(line) => {
+                    let mut position = json!({
+                        "position_type": "text",
+                        "base_sha": review.base_sha,
+                        "start_sha": review.base_sha,
+                        "head_sha": review.head_sha,
+                        "old_path": old_path(diff, &thread.file_path),
+                        "new_path": thread.file_path,
+                        "new_line": thread.line_number,
+                    });
+                    if line.line_type == LineType::Context {
+                        position["old_line"] = json!(line.old_line_no);
+                    }
+                    json!({ "body": body, "position": position })
+                }
+                None => json!({
+                    "body": format!("`{}:{}`\n\n{}", thread.file_path, thread.line_number, body),
+                }),
+            }
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&discussions)? + "\n")
+}
+
+/// Path of a file before the change, from its `rename from` or `---`
+/// header
+fn old_path<'a>(diff: &'a [DiffLine], path: &'a str) -> &'a str {
+    diff.iter()
+        .filter(|l| l.file_path == path && l.line_type == LineType::FileHeader)
+        .find_map(|l| {
+            l.content
+                .strip_prefix("rename from ")
+                .or_else(|| l.content.strip_prefix("--- a/"))
+        })
+        .map_or(path, |p| p.split('\t').next().unwrap_or(p))
+}
+
+/// The diff line a comment is anchored to; the commit message isn't part of
+/// a merge request's diff
+fn diff_line<'a>(diff: &'a [DiffLine], comment: &Comment) -> Option<&'a DiffLine> {
+    diff.iter().find(|l| {
+        l.file_path == comment.file_path
+            && l.file_path != COMMIT_MSG
+            && l.line_type != LineType::Removed
+            && l.new_line_no == Some(comment.line_number)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::fixture;
+
+    #[test]
+    fn context_lines_carry_both_line_numbers() {
+        let mut added = Comment::new("src/lib.rs".into(), 23, "Why 2?".into(), Vec::new());
+        added.author = Some("Ann <ann@example.com>".into());
+        let comments = [
+            Comment::new("src/lib.rs".into(), 22, "Unused".into(), Vec::new()),
+            added,
+            Comment::new(
+                "src/lib.rs".into(),
+                10,
+                "Not in the diff".into(),
+                Vec::new(),
+            ),
+        ];
+        let review = ReviewInfo {
+            base_sha: Some("base".into()),
+            head_sha: Some("head".into()),
+            ..ReviewInfo::default()
+        };
+        let threads: Value =
+            serde_json::from_str(&render(&comments, &review, &fixture::diff()).unwrap()).unwrap();
+        assert_eq!(threads[1]["position"]["old_path"], "src/lib.rs");
+
+        // Threads are sorted by line
+        assert!(threads[0].get("position").is_none());
+        assert_eq!(threads[0]["body"], "`src/lib.rs:10`\n\nNot in the diff");
+
+        let context = &threads[1]["position"];
+        assert_eq!(
+            (&context["old_line"], &context["new_line"]),
+            (&json!(20), &json!(22))
+        );
+        assert_eq!(
+            (&context["base_sha"], &context["head_sha"]),
+            (&json!("base"), &json!("head"))
+        );
+
+        let added = &threads[2];
+        assert!(added["position"].get("old_line").is_none());
+        assert_eq!(added["position"]["new_line"], 23);
+        assert_eq!(added["body"], "**Ann:** Why 2?");
+    }
+
+    #[test]
+    fn renamed_files_keep_their_old_path() {
+        let renamed = format!(
+            "{}{}",
+            include_str!("../../tests/fixtures/review.diff"),
+            "diff --git a/src/old.rs b/src/new.rs\n\
+             similarity index 80%\n\
+             rename from src/old.rs\n\
+             rename to src/new.rs\n\
+             --- a/src/old.rs\n\
+             +++ b/src/new.rs\n\
+             @@ -1,2 +1,2 @@\n fn a() {}\n-fn b() {}\n+fn c() {}\n"
+        );
+        let diff = crate::diff::parse_diff(&renamed).unwrap();
+        let comments = [Comment::new(
+            "src/new.rs".into(),
+            1,
+            "Moved".into(),
+            Vec::new(),
+        )];
+        let threads: Value =
+            serde_json::from_str(&render(&comments, &ReviewInfo::default(), &diff).unwrap())
+                .unwrap();
+
+        let position = &threads[0]["position"];
+        assert_eq!(
+            (&position["old_path"], &position["new_path"]),
+            (&json!("src/old.rs"), &json!("src/new.rs"))
+        );
+        assert_eq!(
+            (&position["old_line"], &position["new_line"]),
+            (&json!(1), &json!(1))
+        );
+    }
+}
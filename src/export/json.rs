@@ -0,0 +1,228 @@
+use super::ReviewInfo;
+use crate::comment::{Category, Comment, CommentStatus, ContextLine, Severity, Suggestion};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Bumped on incompatible changes to schema/review.v1.schema.json
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// --format json: one document holding every comment
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    review: &'a ReviewInfo,
+    comments: Vec<JsonComment<'a>>,
+}
+
+/// --format jsonl: one self-contained record per comment
+#[derive(Serialize)]
+struct Record<'a> {
+    schema_version: u32,
+    review: &'a ReviewInfo,
+    #[serde(flatten)]
+    comment: JsonComment<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonComment<'a> {
+    id: Uuid,
+    in_reply_to: Option<Uuid>,
+    file: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<&'a str>, // Only on comments made in a range diff
+    side: &'static str, // Comments are always on the new side of the diff
+    line: LineRange,
+    text: &'a str,
+    author: Option<&'a str>,
+    timestamp: DateTime<Utc>,
+    edited_at: Option<DateTime<Utc>>,
+    status: CommentStatus,
+    severity: Option<Severity>,
+    category: Option<Category>,
+    suggestion: Option<&'a Suggestion>,
+    context: Vec<JsonContextLine<'a>>,
+}
+
+#[derive(Serialize)]
+struct LineRange {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize)]
+struct JsonContextLine<'a> {
+    #[serde(rename = "type")]
+    line_type: &'static str, // "added", "removed" or "context"
+    old_line: Option<usize>,
+    new_line: Option<usize>,
+    content: &'a str, // Without the diff marker
+}
+
+impl<'a> From<&'a Comment> for JsonComment<'a> {
+    fn from(c: &'a Comment) -> Self {
+        Self {
+            id: c.id,
+            in_reply_to: c.in_reply_to,
+            file: &c.file_path,
+            commit: c.commit.as_deref(),
+            side: "new",
+            line: LineRange {
+                start: c.line_number,
+                end: c.suggestion.as_ref().map_or(c.line_number, |s| s.end_line),
+            },
+            text: &c.text,
+            author: c.author.as_deref(),
+            timestamp: c.timestamp,
+            edited_at: c.edited_at,
+            status: c.status,
+            severity: c.severity,
+            category: c.category,
+            suggestion: c.suggestion.as_ref(),
+            context: c.context.iter().map(JsonContextLine::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a ContextLine> for JsonContextLine<'a> {
+    fn from(ctx: &'a ContextLine) -> Self {
+        let line_type = match ctx.content.chars().next() {
+            Some('+') => "added",
+            Some('-') => "removed",
+            _ => "context",
+        };
+        Self {
+            line_type,
+            old_line: ctx.old_line_no,
+            new_line: ctx.new_line_no,
+            content: ctx.content.get(1..).unwrap_or(""),
+        }
+    }
+}
+
+/// Comments as they appear in the JSON export, for other JSON APIs
+pub fn comment_values(comments: &[Comment]) -> Result<serde_json::Value> {
+    let comments: Vec<JsonComment> = comments.iter().map(JsonComment::from).collect();
+    Ok(serde_json::to_value(comments)?)
+}
+
+pub fn render(comments: &[Comment], review: &ReviewInfo) -> Result<String> {
+    let document = Document {
+        schema_version: SCHEMA_VERSION,
+        review,
+        comments: comments.iter().map(JsonComment::from).collect(),
+    };
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+pub fn render_lines(comments: &[Comment], review: &ReviewInfo) -> Result<String> {
+    let mut out = String::new();
+    for c in comments {
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            review,
+            comment: JsonComment::from(c),
+        };
+        out.push_str(&serde_json::to_string(&record)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::COMMIT_MSG;
+    use crate::export::fixture;
+    use serde_json::Value;
+
+    fn validator() -> jsonschema::Validator {
+        let schema: Value =
+            serde_json::from_str(include_str!("../../schema/review.v1.schema.json")).unwrap();
+        jsonschema::validator_for(&schema).unwrap()
+    }
+
+    fn assert_valid(validator: &jsonschema::Validator, instance: &Value) {
+        let errors: Vec<String> = validator
+            .iter_errors(instance)
+            .map(|e| format!("{} at {}", e, e.instance_path))
+            .collect();
+        assert!(errors.is_empty(), "{:#?}", errors);
+    }
+
+    fn comments() -> Vec<Comment> {
+        let mut nit = fixture::suggestion();
+        nit.severity = Some(Severity::Nit);
+        nit.category = Some(Category::Style);
+        nit.author = Some("Ann <ann@example.com>".into());
+        let reply = nit.reply("Agreed".into());
+        let mut resolved = Comment::new("src/main.rs".into(), 2, "Why?".into(), Vec::new());
+        resolved.status = CommentStatus::Resolved;
+        let message = Comment::new(COMMIT_MSG.into(), 1, "Typo".into(), Vec::new());
+        vec![nit, reply, resolved, message]
+    }
+
+    #[test]
+    fn documents_and_records_match_the_schema() {
+        let review = ReviewInfo {
+            vcs: Some("git"),
+            vcs_ref: Some("HEAD".into()),
+            base_sha: Some("base".into()),
+            head_sha: Some("head".into()),
+        };
+        let validator = validator();
+        assert!(!validator.is_valid(&serde_json::json!({ "schema_version": 2 })));
+
+        let document: Value = serde_json::from_str(&render(&comments(), &review).unwrap()).unwrap();
+        assert_valid(&validator, &document);
+        let files: Vec<&str> = document["comments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            files,
+            ["src/lib.rs", "src/lib.rs", "src/main.rs", COMMIT_MSG]
+        );
+        assert_eq!(
+            document["comments"][0]["line"],
+            serde_json::json!({ "start": 2, "end": 4 })
+        );
+        assert_eq!(document["comments"][0]["context"][1]["type"], "added");
+
+        // Uncommitted changes have no ref or commits
+        let lines = render_lines(&comments(), &ReviewInfo::default()).unwrap();
+        assert_eq!(lines.lines().count(), 4);
+        for line in lines.lines() {
+            assert_valid(&validator, &serde_json::from_str(line).unwrap());
+        }
+    }
+
+    #[test]
+    fn exports_import_back_with_commit_message_comments() {
+        let (exported, review) = (comments(), ReviewInfo::default());
+        for (name, rendered) in [
+            ("json", render(&exported, &review).unwrap()),
+            ("jsonl", render_lines(&exported, &review).unwrap()),
+        ] {
+            let path =
+                std::env::temp_dir().join(format!("vr-json-test-{}.{}", Uuid::new_v4(), name));
+            std::fs::write(&path, rendered).unwrap();
+            let imported = crate::import::load(&path, std::path::Path::new("/repo")).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(imported.len(), exported.len(), "{}", name);
+            for (back, c) in imported.iter().zip(&exported) {
+                assert_eq!(
+                    (back.id, &back.file_path, back.line_number, &back.text),
+                    (c.id, &c.file_path, c.line_number, &c.text),
+                    "{}",
+                    name
+                );
+                assert_eq!(back.in_reply_to, c.in_reply_to);
+            }
+        }
+    }
+}
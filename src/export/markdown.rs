@@ -0,0 +1,175 @@
+use super::{language, quoted_replies};
+use crate::comment::{self, Comment, CommentStatus, Severity};
+use std::collections::BTreeMap;
+
+/// Render a Markdown review report: a summary table of comment counts by
+/// file and severity, then one section per file with each thread's diff
+/// context in a fenced block
+pub fn render(comments: &[Comment]) -> String {
+    let mut by_file: BTreeMap<&str, Vec<&Comment>> = BTreeMap::new();
+    for c in comments
+        .iter()
+        .filter(|c| comment::is_thread_root(c, comments))
+    {
+        by_file.entry(c.file_path.as_str()).or_default().push(c);
+    }
+    for threads in by_file.values_mut() {
+        threads.sort_by_key(|c| c.line_number);
+    }
+
+    let mut out = String::from("# Code Review\n\n");
+    if by_file.is_empty() {
+        out.push_str("No comments.\n");
+        return out;
+    }
+
+    out.push_str(&summary_table(&by_file));
+
+    for (file, threads) in &by_file {
+        out.push_str(&format!("\n## `{}`\n", file));
+        for thread in threads {
+            out.push('\n');
+            out.push_str(&render_thread(thread, comments));
+        }
+    }
+
+    out
+}
+
+/// Counts of threads per file, one column per severity (most severe first)
+fn summary_table(by_file: &BTreeMap<&str, Vec<&Comment>>) -> String {
+    let columns: Vec<Option<Severity>> = Severity::ALL
+        .into_iter()
+        .rev()
+        .map(Some)
+        .chain([None])
+        .collect();
+    let count = |threads: &[&Comment], severity: Option<Severity>| {
+        threads.iter().filter(|c| c.severity == severity).count()
+    };
+
+    let mut out = String::from("| File |");
+    for severity in &columns {
+        out.push_str(&format!(
+            " {} |",
+            severity.map_or("unlabelled", |s| s.label())
+        ));
+    }
+    out.push_str(" Total |\n|---|");
+    out.push_str(&"---:|".repeat(columns.len() + 1));
+    out.push('\n');
+
+    let mut all = Vec::new();
+    for (file, threads) in by_file {
+        out.push_str(&format!("| `{}` |", file));
+        for &severity in &columns {
+            out.push_str(&format!(" {} |", count(threads, severity)));
+        }
+        out.push_str(&format!(" {} |\n", threads.len()));
+        all.extend(threads.iter().copied());
+    }
+
+    out.push_str("| **Total** |");
+    for &severity in &columns {
+        out.push_str(&format!(" **{}** |", count(&all, severity)));
+    }
+    out.push_str(&format!(" **{}** |\n", all.len()));
+    out
+}
+
+fn render_thread(thread: &Comment, comments: &[Comment]) -> String {
+    // Heading: line, labels, status and author
+    let mut out = format!("### Line {}", thread.line_number);
+    if let Some(s) = &thread.suggestion {
+        if s.end_line > thread.line_number {
+            out = format!("### Lines {}-{}", thread.line_number, s.end_line);
+        }
+    }
+    for label in thread.labels() {
+        out.push_str(&format!(" `{}`", label));
+    }
+    if thread.status != CommentStatus::Open {
+        out.push_str(&format!(" ({})", thread.status.label()));
+    }
+    if let Some(name) = thread.author_name() {
+        out.push_str(&format!(" — {}", name));
+    }
+    out.push('\n');
+
+    // The fence is a diff so +/- lines are highlighted; the language follows
+    // for renderers that can highlight both
+    if !thread.context.is_empty() {
+        let info = match language(&thread.file_path) {
+            Some(lang) => format!("diff {}", lang),
+            None => "diff".to_string(),
+        };
+        out.push_str(&format!("\n```{}\n", info));
+        for ctx in &thread.context {
+            out.push_str(&ctx.content);
+            out.push('\n');
+        }
+        out.push_str("```\n");
+    }
+
+    out.push('\n');
+    out.push_str(&thread.body());
+    out.push('\n');
+
+    out.push_str(&quoted_replies(thread, comments));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Category;
+    use crate::export::fixture;
+
+    #[test]
+    fn threads_labels_and_suggestions() {
+        let mut nit = fixture::suggestion();
+        nit.severity = Some(Severity::Nit);
+        nit.category = Some(Category::Style);
+        nit.author = Some("Ann <ann@example.com>".into());
+        let mut reply = nit.reply("Agreed".into());
+        reply.author = Some("Bob".into());
+        let mut resolved = Comment::new("src/main.rs".into(), 2, "Why?".into(), Vec::new());
+        resolved.status = CommentStatus::Resolved;
+
+        assert_eq!(
+            render(&[resolved, nit, reply]),
+            "\
+# Code Review
+
+| File | blocker | issue | suggestion | nit | unlabelled | Total |
+|---|---:|---:|---:|---:|---:|---:|
+| `src/lib.rs` | 0 | 0 | 0 | 1 | 0 | 1 |
+| `src/main.rs` | 0 | 0 | 0 | 0 | 1 | 1 |
+| **Total** | **0** | **0** | **0** | **1** | **1** | **2** |
+
+## `src/lib.rs`
+
+### Lines 2-4 `nit` `style` — Ann
+
+```diff rust
+ fn a() {}
++fn b() -> u8 {
+```
+
+Inline it
+
+```suggestion
+fn b() -> u8 { 1 }
+```
+
+> **Bob:** Agreed
+
+## `src/main.rs`
+
+### Line 2 (resolved)
+
+Why?
+"
+        );
+    }
+}
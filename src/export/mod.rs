@@ -0,0 +1,296 @@
+mod gerrit;
+mod github;
+mod gitlab;
+mod json;
+mod markdown;
+mod phabricator;
+mod prompt;
+mod quickfix;
+mod rdjson;
+mod sarif;
+
+pub use json::comment_values;
+pub use prompt::{check as check_template, DEFAULT_TEMPLATE};
+pub use quickfix::ERRORFORMAT;
+
+use crate::comment::{self, Comment, Severity};
+use crate::diff::DiffLine;
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Output format selected with --format
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Markdown,
+    Json,
+    JsonLines,
+    Sarif,
+    Quickfix,
+    GitHub,
+    GitHubPosition,
+    GitLab,
+    Gerrit,
+    Phabricator,
+    Rdjson,
+    Rdjsonl,
+    Prompt(String), // Name of the template
+}
+
+impl Format {
+    /// Every format but `prompt`, which also needs a template
+    const FIXED: [Format; 13] = [
+        Format::Text,
+        Format::Markdown,
+        Format::Json,
+        Format::JsonLines,
+        Format::Sarif,
+        Format::Quickfix,
+        Format::GitHub,
+        Format::GitHubPosition,
+        Format::GitLab,
+        Format::Gerrit,
+        Format::Phabricator,
+        Format::Rdjson,
+        Format::Rdjsonl,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Markdown => "markdown",
+            Format::Json => "json",
+            Format::JsonLines => "jsonl",
+            Format::Sarif => "sarif",
+            Format::Quickfix => "quickfix",
+            Format::GitHub => "github",
+            Format::GitHubPosition => "github-position",
+            Format::GitLab => "gitlab",
+            Format::Gerrit => "gerrit",
+            Format::Phabricator => "phabricator",
+            Format::Rdjson => "rdjson",
+            Format::Rdjsonl => "rdjsonl",
+            Format::Prompt(_) => "prompt",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "md" => Some(Format::Markdown),
+            "txt" => Some(Format::Text),
+            "qf" => Some(Format::Quickfix),
+            "phab" => Some(Format::Phabricator),
+            "prompt" => Some(Format::Prompt(DEFAULT_TEMPLATE.to_string())),
+            s => Self::FIXED.into_iter().find(|f| f.name() == s),
+        }
+    }
+
+    /// File extension of the output file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Markdown => "md",
+            Format::Json => "json",
+            Format::JsonLines => "jsonl",
+            Format::Sarif => "sarif",
+            Format::Quickfix => "qf",
+            Format::GitHub | Format::GitHubPosition => "github.json",
+            Format::GitLab => "gitlab.json",
+            Format::Gerrit => "gerrit.json",
+            Format::Phabricator => "phabricator.jsonl",
+            Format::Rdjson => "rdjson",
+            Format::Rdjsonl => "rdjsonl",
+            Format::Prompt(_) => "prompt.md",
+        }
+    }
+}
+
+/// What was reviewed, recorded in machine-readable exports
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReviewInfo {
+    pub vcs: Option<&'static str>, // "git" or "hg"
+    #[serde(rename = "ref")]
+    pub vcs_ref: Option<String>, // As given on the command line
+    pub base_sha: Option<String>,
+    pub head_sha: Option<String>, // None for uncommitted changes
+}
+
+/// Write comments to `path` in the given format
+///
+/// `diff` is the diff being reviewed, which code host payloads use to place
+/// comments; it may be empty when there is none.
+pub fn write(
+    format: &Format,
+    comments: &[Comment],
+    review: &ReviewInfo,
+    diff: &[DiffLine],
+    path: &str,
+) -> Result<()> {
+    let content = match format {
+        Format::Text => return comment::save_comments_to_file(comments, path),
+        Format::Markdown => markdown::render(comments),
+        Format::Json => json::render(comments, review)?,
+        Format::JsonLines => json::render_lines(comments, review)?,
+        Format::Sarif => sarif::render(comments, review)?,
+        Format::Quickfix => quickfix::render(comments),
+        Format::GitHub => github::render(comments, review, diff, github::Anchor::Line)?,
+        Format::GitHubPosition => github::render(comments, review, diff, github::Anchor::Position)?,
+        Format::GitLab => gitlab::render(comments, review, diff)?,
+        Format::Gerrit => gerrit::render(comments, diff)?,
+        Format::Phabricator => phabricator::render(comments)?,
+        Format::Rdjson => rdjson::render(comments)?,
+        Format::Rdjsonl => rdjson::render_lines(comments)?,
+        Format::Prompt(template) => prompt::render(template, comments, review, diff)?,
+    };
+
+    // Create parent directory if it doesn't exist
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+
+    Ok(())
+}
+
+/// Replies to a thread as a quoted Markdown conversation
+fn quoted_replies(thread: &Comment, comments: &[Comment]) -> String {
+    let mut out = String::new();
+    for reply in comment::replies_to(thread, comments) {
+        out.push('\n');
+        let author = reply.author_name().unwrap_or("reply");
+        for (n, line) in reply.text.lines().enumerate() {
+            if n == 0 {
+                out.push_str(&format!("> **{}:** {}\n", author, line));
+            } else {
+                out.push_str(&format!("> {}\n", line));
+            }
+        }
+    }
+    out
+}
+
+/// Markdown body of a thread for posting to a code host: the author (the
+/// host would show whoever posts the review), the labels, the text, the
+/// suggestion in a block opened with `fence` (unless the host takes
+/// suggestions separately), then the replies
+fn hosted_body(thread: &Comment, comments: &[Comment], fence: Option<&str>) -> String {
+    let mut body = thread
+        .author_name()
+        .map(|name| format!("**{}:** ", name))
+        .unwrap_or_default();
+    body.extend(thread.labels().iter().map(|l| format!("**[{}]** ", l)));
+    body.push_str(&thread.text);
+    if let (Some(s), Some(fence)) = (&thread.suggestion, fence) {
+        if !thread.text.is_empty() {
+            body.push_str("\n\n");
+        }
+        body.push_str(&format!("{}\n{}\n```", fence, s.replacement));
+    }
+    body.push('\n');
+    body.push_str(&quoted_replies(thread, comments));
+    body.trim_end().to_string()
+}
+
+/// e.g. "Review from vr: 3 comments (1 blocker, 2 nits)"
+fn summary(comments: &[Comment]) -> String {
+    let threads = threads(comments);
+    let counts: Vec<String> = Severity::ALL
+        .iter()
+        .rev()
+        .filter_map(|&sev| {
+            let n = threads.iter().filter(|c| c.severity == Some(sev)).count();
+            (n > 0).then(|| format!("{} {}{}", n, sev.label(), if n == 1 { "" } else { "s" }))
+        })
+        .collect();
+
+    let mut summary = format!(
+        "Review from vr: {} comment{}",
+        threads.len(),
+        if threads.len() == 1 { "" } else { "s" }
+    );
+    if !counts.is_empty() {
+        summary.push_str(&format!(" ({})", counts.join(", ")));
+    }
+    summary
+}
+
+/// Thread roots, ordered by file and line
+fn threads(comments: &[Comment]) -> Vec<&Comment> {
+    let mut threads: Vec<&Comment> = comments
+        .iter()
+        .filter(|c| comment::is_thread_root(c, comments))
+        .collect();
+    threads.sort_by(|a, b| (&a.file_path, a.line_number).cmp(&(&b.file_path, b.line_number)));
+    threads
+}
+
+/// Language name for a file, as used in fenced code blocks
+pub fn language(file_path: &str) -> Option<&'static str> {
+    let extension = Path::new(file_path).extension()?.to_str()?;
+    let language = match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "sh" | "bash" => "bash",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "md" => "markdown",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Review of tests/fixtures/review.diff shared by the exporters' tests
+#[cfg(test)]
+mod fixture {
+    use crate::comment::{Comment, ContextLine, Suggestion};
+    use crate::diff::{self, DiffLine};
+
+    pub(super) fn diff() -> Vec<DiffLine> {
+        diff::parse_diff(include_str!("../../tests/fixtures/review.diff")).unwrap()
+    }
+
+    /// Thread on src/lib.rs:2 suggesting `fn b` go on one line, with the
+    /// context vr stores for it
+    pub(super) fn suggestion() -> Comment {
+        let context = [
+            (" fn a() {}", Some(1), Some(1)),
+            ("+fn b() -> u8 {", None, Some(2)),
+        ]
+        .into_iter()
+        .map(|(content, old_line_no, new_line_no)| ContextLine {
+            content: content.to_string(),
+            prefix: String::new(),
+            old_line_no,
+            new_line_no,
+        })
+        .collect();
+        let mut comment = Comment::new("src/lib.rs".into(), 2, "Inline it".into(), context);
+        comment.suggestion = Some(Suggestion {
+            end_line: 4,
+            original: vec!["fn b() -> u8 {".into(), "    1".into(), "}".into()],
+            replacement: "fn b() -> u8 { 1 }".into(),
+        });
+        comment
+    }
+}
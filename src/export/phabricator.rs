@@ -0,0 +1,34 @@
+use super::{hosted_body, threads};
+use crate::comment::Comment;
+use crate::diff::COMMIT_MSG;
+use anyhow::Result;
+use serde_json::json;
+
+/// Parameters for Phabricator's `differential.createinline` Conduit method,
+/// one JSON object per line so a script can make one call per comment
+///
+/// Phabricator has no inline comments on the commit message, so those are
+/// skipped. `revisionID` is left out since vr doesn't know the revision;
+/// add it before calling, e.g. with `jq -c '. + {revisionID: 123}'`.
+pub fn render(comments: &[Comment]) -> Result<String> {
+    let mut out = String::new();
+    for thread in threads(comments)
+        .into_iter()
+        .filter(|c| c.file_path != COMMIT_MSG)
+    {
+        let end_line = thread
+            .suggestion
+            .as_ref()
+            .map_or(thread.line_number, |s| s.end_line);
+        let params = json!({
+            "filePath": thread.file_path,
+            "isNewFile": true,
+            "lineNumber": thread.line_number,
+            "lineLength": end_line - thread.line_number,
+            "content": hosted_body(thread, comments, Some("Suggested change:\n```")),
+        });
+        out.push_str(&serde_json::to_string(&params)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
@@ -0,0 +1,261 @@
+use super::{language, summary, threads, ReviewInfo};
+use crate::comment::{self, Comment, CommentStatus};
+use crate::diff::{DiffLine, LineType};
+use anyhow::{Context, Result};
+use minijinja::Environment;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Template used by --format prompt
+pub const DEFAULT_TEMPLATE: &str = "agent";
+
+/// Where a review's own templates live; they take precedence over the
+/// built-in ones of the same name
+const TEMPLATE_DIR: &str = ".vim-review/templates";
+
+/// Templates shipped with vr, tuned for handing a review to a coding agent
+const BUILTIN: [(&str, &str); 2] = [
+    ("agent", include_str!("templates/agent.jinja")),
+    ("agent-diff", include_str!("templates/agent-diff.jinja")),
+];
+
+/// Everything a template can use
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    review: &'a ReviewInfo,
+    root: String, // Repository root
+    summary: String,
+    open_summary: String,      // The same, counting only open threads
+    comments: Vec<Thread<'a>>, // Thread roots ordered by file and line
+    files: Vec<File<'a>>,
+    diff: String, // The whole diff under review
+}
+
+#[derive(Serialize)]
+struct Thread<'a> {
+    id: String,
+    file: &'a str,
+    line: usize,
+    end_line: usize,
+    language: &'static str, // For fenced code blocks; empty if unknown
+    text: &'a str,
+    labels: Vec<&'static str>,
+    severity: Option<&'static str>,
+    category: Option<&'static str>,
+    status: &'static str,
+    author: Option<&'a str>,
+    context: String, // Diff lines up to the comment, with their markers
+    suggestion: Option<&'a comment::Suggestion>,
+    replies: Vec<Reply<'a>>,
+}
+
+#[derive(Serialize)]
+struct Reply<'a> {
+    author: Option<&'a str>,
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+struct File<'a> {
+    path: &'a str,
+    added: usize,
+    removed: usize,
+    diff: String,
+}
+
+/// Render the comments with the named template from .vim-review/templates/
+/// (`<name>` or `<name>.jinja`), or the built-in one of that name
+pub fn render(
+    template: &str,
+    comments: &[Comment],
+    review: &ReviewInfo,
+    diff: &[DiffLine],
+) -> Result<String> {
+    let env = environment();
+    let root = crate::vcs::repo_root()
+        .map(|root| root.display().to_string())
+        .unwrap_or_default();
+    let open: Vec<Comment> = threads(comments)
+        .into_iter()
+        .filter(|c| c.status == CommentStatus::Open)
+        .cloned()
+        .collect();
+    let context = TemplateContext {
+        review,
+        root,
+        summary: summary(comments),
+        open_summary: summary(&open),
+        comments: threads(comments)
+            .into_iter()
+            .map(|c| Thread::new(c, comments))
+            .collect(),
+        files: files(diff),
+        diff: diff_text(diff.iter()),
+    };
+
+    env.get_template(template)
+        .and_then(|t| t.render(&context))
+        .with_context(|| format!("Failed to render template '{}'", template))
+}
+
+/// Fail early if the template doesn't exist or doesn't parse
+pub fn check(template: &str) -> Result<()> {
+    environment()
+        .get_template(template)
+        .with_context(|| format!("Invalid template '{}'", template))?;
+    Ok(())
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    // Templates can include or extend each other, built-in ones included
+    env.set_loader(|name| {
+        let dir = Path::new(TEMPLATE_DIR);
+        for path in [dir.join(name), dir.join(format!("{}.jinja", name))] {
+            if path.is_file() {
+                return fs::read_to_string(&path).map(Some).map_err(|e| {
+                    minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        format!("Failed to read {}: {}", path.display(), e),
+                    )
+                });
+            }
+        }
+        Ok(BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, source)| source.to_string()))
+    });
+    env
+}
+
+impl<'a> Thread<'a> {
+    fn new(c: &'a Comment, comments: &'a [Comment]) -> Self {
+        Self {
+            id: c.id.to_string(),
+            file: &c.file_path,
+            line: c.line_number,
+            end_line: c.suggestion.as_ref().map_or(c.line_number, |s| s.end_line),
+            language: language(&c.file_path).unwrap_or_default(),
+            text: &c.text,
+            labels: c.labels(),
+            severity: c.severity.map(|s| s.label()),
+            category: c.category.map(|cat| cat.label()),
+            status: c.status.label(),
+            author: c.author_name(),
+            context: c
+                .context
+                .iter()
+                .map(|ctx| ctx.content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            suggestion: c.suggestion.as_ref(),
+            replies: comment::replies_to(c, comments)
+                .map(|r| Reply {
+                    author: r.author_name(),
+                    text: &r.text,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Each file's part of the diff, in diff order
+fn files(diff: &[DiffLine]) -> Vec<File<'_>> {
+    let mut files: Vec<File> = Vec::new();
+    for (idx, line) in diff.iter().enumerate() {
+        if files.last().is_none_or(|f| f.path != line.file_path) {
+            let lines = diff[idx..]
+                .iter()
+                .take_while(|l| l.file_path == line.file_path);
+            files.push(File {
+                path: &line.file_path,
+                added: lines
+                    .clone()
+                    .filter(|l| l.line_type == LineType::Added)
+                    .count(),
+                removed: lines
+                    .clone()
+                    .filter(|l| l.line_type == LineType::Removed)
+                    .count(),
+                diff: diff_text(lines),
+            });
+        }
+    }
+    files
+}
+
+fn diff_text<'a>(lines: impl Iterator<Item = &'a DiffLine>) -> String {
+    lines
+        .map(|l| l.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Severity;
+    use crate::export::fixture;
+
+    fn comments() -> Vec<Comment> {
+        let mut nit = fixture::suggestion();
+        nit.severity = Some(Severity::Nit);
+        let reply = nit.reply("Agreed".into());
+        let mut resolved = Comment::new("src/main.rs".into(), 2, "Use it".into(), Vec::new());
+        resolved.severity = Some(Severity::Blocker);
+        resolved.status = CommentStatus::Resolved;
+        vec![nit, reply, resolved]
+    }
+
+    #[test]
+    fn agent_lists_the_open_threads() {
+        let review = ReviewInfo {
+            vcs_ref: Some("HEAD".into()),
+            ..ReviewInfo::default()
+        };
+        let prompt = render("agent", &comments(), &review, &fixture::diff()).unwrap();
+        assert!(prompt.starts_with("You are addressing code review comments on `HEAD`"));
+        // The summary counts the threads the agent is asked about
+        assert!(prompt.contains("\nReview from vr: 1 comment (1 nit)\n"));
+        assert!(prompt.ends_with(
+            "\
+## 1. `src/lib.rs:2` [nit]
+
+```diff
+ fn a() {}
++fn b() -> u8 {
+```
+
+Inline it
+
+Suggested replacement for lines 2-4:
+
+```rust
+fn b() -> u8 { 1 }
+```
+
+> reply: Agreed
+"
+        ));
+        assert!(!prompt.contains("Use it"));
+        assert!(!prompt.contains("## Full diff"));
+    }
+
+    #[test]
+    fn agent_diff_adds_the_diff() {
+        let diff = fixture::diff();
+        let prompt = render("agent-diff", &comments(), &ReviewInfo::default(), &diff).unwrap();
+        assert!(prompt
+            .starts_with("You are addressing code review comments on the uncommitted changes"));
+        assert!(prompt.contains("## 1. `src/lib.rs:2` [nit]"));
+        assert!(prompt.ends_with(&format!(
+            "## Full diff\n\n```diff\n{}\n```\n",
+            diff_text(diff.iter())
+        )));
+    }
+}
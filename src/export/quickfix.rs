@@ -0,0 +1,98 @@
+use super::threads;
+use crate::comment::{self, Comment, CommentStatus};
+use crate::diff::COMMIT_MSG;
+
+/// 'errorformat' that reads continuation lines back into the message:
+/// `%A` starts an entry and `%C` appends lines indented by four spaces
+pub const ERRORFORMAT: &str = "%A%f:%l:%c: %m,%C    %m";
+
+/// Render comment threads as `path:line:col: text` lines for vim's quickfix
+/// list, sorted by file and line, with the labels, status and `@author` in
+/// tags ahead of the text; comments on the commit message are left out
+///
+/// Further lines of the text, the suggestion and the replies follow as lines
+/// indented by four spaces. With the default 'errorformat' they show up as
+/// text-only entries under their comment; with [`ERRORFORMAT`] they become
+/// part of its message.
+pub fn render(comments: &[Comment]) -> String {
+    let mut out = String::new();
+    // vim can't open a commit message's /COMMIT_MSG
+    for c in threads(comments)
+        .into_iter()
+        .filter(|c| c.file_path != COMMIT_MSG)
+    {
+        let mut tags: Vec<String> = c.labels().iter().map(|l| l.to_string()).collect();
+        if c.status != CommentStatus::Open {
+            tags.push(c.status.label().to_string());
+        }
+        // The entry can only point at one line, so the lines a suggestion
+        // replaces are kept in a tag
+        if let Some(s) = c.suggestion.as_ref().filter(|s| s.end_line > c.line_number) {
+            tags.push(format!("L{}-L{}", c.line_number, s.end_line));
+        }
+        if let Some(name) = c.author_name() {
+            tags.push(format!("@{}", name));
+        }
+        let tags: String = tags.iter().map(|t| format!("[{}] ", t)).collect();
+
+        let mut lines: Vec<String> = c.body().lines().map(str::to_string).collect();
+        for reply in comment::replies_to(c, comments) {
+            let author = reply.author_name().unwrap_or("reply");
+            for (n, line) in reply.text.lines().enumerate() {
+                if n == 0 {
+                    lines.push(format!("↳ {}: {}", author, line));
+                } else {
+                    lines.push(format!("  {}", line));
+                }
+            }
+        }
+
+        let first = lines.first().map_or("", String::as_str);
+        out.push_str(&format!(
+            "{}:{}:{}: {}{}\n",
+            c.file_path,
+            c.line_number,
+            column(c),
+            tags,
+            first
+        ));
+        for line in lines.iter().skip(1) {
+            out.push_str(&format!("    {}\n", line));
+        }
+    }
+
+    out
+}
+
+/// 1-based column of the first non-blank character of the commented line,
+/// which is the last line of its context
+fn column(c: &Comment) -> usize {
+    let Some(line) = c.context.last() else {
+        return 1;
+    };
+    let code = line.content.get(1..).unwrap_or("");
+    code.chars().take_while(|ch| ch.is_whitespace()).count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn import_reads_back_what_render_writes() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/review.qf");
+        let comments = crate::import::load(&path, Path::new("")).unwrap();
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(render(&comments), fixture);
+    }
+
+    #[test]
+    fn commit_message_is_left_out() {
+        let comments = [
+            Comment::new(COMMIT_MSG.into(), 1, "Typo".into(), Vec::new()),
+            Comment::new("src/lib.rs".into(), 2, "Inline it".into(), Vec::new()),
+        ];
+        assert_eq!(render(&comments), "src/lib.rs:2:1: Inline it\n");
+    }
+}
@@ -0,0 +1,123 @@
+use super::{hosted_body, threads};
+use crate::comment::{Comment, Severity};
+use crate::diff::COMMIT_MSG;
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// --format rdjson: a reviewdog DiagnosticResult holding every thread
+pub fn render(comments: &[Comment]) -> Result<String> {
+    let result = json!({
+        "source": source(),
+        "diagnostics": file_threads(comments)
+            .map(|c| diagnostic(c, comments))
+            .collect::<Vec<_>>(),
+    });
+    Ok(serde_json::to_string_pretty(&result)? + "\n")
+}
+
+/// --format rdjsonl: one reviewdog Diagnostic per line
+pub fn render_lines(comments: &[Comment]) -> Result<String> {
+    let mut out = String::new();
+    for c in file_threads(comments) {
+        out.push_str(&serde_json::to_string(&diagnostic(c, comments))?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Threads on files; reviewdog would look for the commit message's
+/// /COMMIT_MSG in the repository, so those are left out
+fn file_threads(comments: &[Comment]) -> impl Iterator<Item = &Comment> {
+    threads(comments)
+        .into_iter()
+        .filter(|c| c.file_path != COMMIT_MSG)
+}
+
+fn source() -> Value {
+    json!({ "name": "vr", "url": env!("CARGO_PKG_REPOSITORY") })
+}
+
+fn severity(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Blocker) => "ERROR",
+        Some(Severity::Issue) | None => "WARNING",
+        Some(Severity::Suggestion) | Some(Severity::Nit) => "INFO",
+    }
+}
+
+fn diagnostic(c: &Comment, comments: &[Comment]) -> Value {
+    let end_line = c.suggestion.as_ref().map_or(c.line_number, |s| s.end_line);
+    let mut diagnostic = json!({
+        "message": hosted_body(c, comments, None),
+        "location": {
+            "path": c.file_path,
+            "range": {
+                "start": { "line": c.line_number },
+                "end": { "line": end_line },
+            },
+        },
+        "severity": severity(c.severity),
+        "source": source(),
+        "code": { "value": format!("review/{}", c.category.map_or("general", |cat| cat.label())) },
+    });
+
+    // Replace whole lines: from the start of the first one to the start of
+    // the line after the last
+    if let Some(s) = &c.suggestion {
+        let mut text = s.replacement.clone();
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        diagnostic["suggestions"] = json!([{
+            "range": {
+                "start": { "line": c.line_number, "column": 1 },
+                "end": { "line": s.end_line + 1, "column": 1 },
+            },
+            "text": text,
+        }]);
+    }
+
+    diagnostic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Category;
+    use crate::export::fixture;
+    use std::path::Path;
+
+    #[test]
+    fn render_lines_matches_the_fixture() {
+        let mut blocker = Comment::new("src/db.rs".into(), 7, "SQL injection".into(), Vec::new());
+        blocker.severity = Some(Severity::Blocker);
+        blocker.category = Some(Category::Security);
+        blocker.author = Some("Bob".into());
+        let mut nit = fixture::suggestion();
+        nit.severity = Some(Severity::Nit);
+        nit.category = Some(Category::Style);
+        nit.author = Some("Ann <ann@example.com>".into());
+        let reply = nit.reply("Agreed".into());
+        let message = Comment::new(COMMIT_MSG.into(), 1, "Typo".into(), Vec::new());
+        let comments = [blocker, nit, reply, message];
+
+        let fixture = include_str!("../../tests/fixtures/review.rdjsonl");
+        assert_eq!(render_lines(&comments).unwrap(), fixture);
+
+        // The document form holds the same diagnostics
+        let result: Value = serde_json::from_str(&render(&comments).unwrap()).unwrap();
+        let lines: Vec<Value> = fixture
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(result["diagnostics"], Value::Array(lines));
+    }
+
+    #[test]
+    fn import_reads_back_what_render_lines_writes() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/review.rdjsonl");
+        let comments = crate::import::load(&path, Path::new("")).unwrap();
+        let fixture = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(render_lines(&comments).unwrap(), fixture);
+    }
+}
@@ -0,0 +1,167 @@
+use super::ReviewInfo;
+use crate::comment::{self, Comment, Severity};
+use crate::diff;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Render a SARIF 2.1.0 log with one result per comment thread
+///
+/// Replies are appended to their thread's message. The rule id comes from
+/// the thread's category, e.g. `review/security`. Comments on the commit
+/// message are left out.
+pub fn render(comments: &[Comment], review: &ReviewInfo) -> Result<String> {
+    let threads: Vec<&Comment> = comments
+        .iter()
+        // A commit message has no artifact to point at
+        .filter(|c| c.file_path != diff::COMMIT_MSG && comment::is_thread_root(c, comments))
+        .collect();
+
+    let rule_ids: BTreeSet<String> = threads.iter().map(|c| rule_id(c)).collect();
+    let rules: Vec<Value> = rule_ids
+        .iter()
+        .map(|id| {
+            let topic = id.trim_start_matches("review/");
+            json!({
+                "id": id,
+                "shortDescription": { "text": format!("Code review comment ({})", topic) },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = threads.iter().map(|c| result(c, comments)).collect();
+
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "vr",
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": { "description": { "text": "Repository root" } }
+            },
+            "properties": review,
+            "results": results,
+        }]
+    });
+
+    Ok(serde_json::to_string_pretty(&log)? + "\n")
+}
+
+fn rule_id(c: &Comment) -> String {
+    format!("review/{}", c.category.map_or("general", |cat| cat.label()))
+}
+
+/// SARIF level for a severity; unlabelled comments use SARIF's default
+fn level(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Blocker) => "error",
+        Some(Severity::Issue) | None => "warning",
+        Some(Severity::Suggestion) | Some(Severity::Nit) => "note",
+    }
+}
+
+fn result(c: &Comment, comments: &[Comment]) -> Value {
+    let mut message = c.body();
+    for reply in comment::replies_to(c, comments) {
+        let author = reply.author_name().unwrap_or("reply");
+        message.push_str(&format!("\n\n↳ {}: {}", author, reply.text));
+    }
+
+    let end_line = c.suggestion.as_ref().map_or(c.line_number, |s| s.end_line);
+
+    json!({
+        "ruleId": rule_id(c),
+        "level": level(c.severity),
+        "message": { "text": message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": { "uri": c.file_path, "uriBaseId": "%SRCROOT%" },
+                "region": { "startLine": c.line_number, "endLine": end_line },
+            }
+        }],
+        "partialFingerprints": { "contextHash/v1": fingerprint(c) },
+        "properties": {
+            "id": c.id,
+            "author": c.author,
+            "status": c.status,
+            "severity": c.severity,
+            "category": c.category,
+            "timestamp": c.timestamp,
+        },
+    })
+}
+
+/// Hash of the file and the diff lines leading up to the comment, which
+/// stays the same across exports while the code is unchanged even if line
+/// numbers shift
+fn fingerprint(c: &Comment) -> String {
+    let lines = std::iter::once(c.file_path.as_str())
+        .chain(c.context.iter().map(|ctx| ctx.content.as_str()));
+    format!("{:016x}", diff::content_hash(lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Category;
+    use crate::export::fixture;
+
+    #[test]
+    fn results_and_rules() {
+        let mut blocker = Comment::new("src/db.rs".into(), 7, "SQL injection".into(), Vec::new());
+        blocker.severity = Some(Severity::Blocker);
+        blocker.category = Some(Category::Security);
+        blocker.author = Some("Ann".into());
+        let reply = blocker.reply("Fixed".into());
+        let mut suggested = fixture::suggestion();
+        suggested.text.clear();
+        let message = Comment::new(diff::COMMIT_MSG.into(), 1, "Typo".into(), Vec::new());
+
+        let log: Value = serde_json::from_str(
+            &render(
+                &[blocker, reply, suggested, message],
+                &ReviewInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let run = &log["runs"][0];
+        let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(rules, ["review/general", "review/security"]);
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["ruleId"], "review/security");
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(
+            results[0]["message"]["text"],
+            "SQL injection\n\n↳ reply: Fixed"
+        );
+        assert_eq!(results[0]["properties"]["author"], "Ann");
+
+        let region = &results[1]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(
+            (&region["startLine"], &region["endLine"]),
+            (&json!(2), &json!(4))
+        );
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(
+            results[1]["message"]["text"],
+            "```suggestion\nfn b() -> u8 { 1 }\n```"
+        );
+    }
+}
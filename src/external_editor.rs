@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::process::Command;
+use uuid::Uuid;
+
+/// Write `header` (as `#` comment lines) and `body` to a temp file, open it in
+/// the user's editor and return the edited body
+///
+/// The header lines are stripped from the result, like git does for commit
+/// messages. The terminal must already be out of raw mode.
+pub fn compose(header: &[String], body: &str) -> Result<String> {
+    let path = env::temp_dir().join(format!("vr-comment-{}.md", Uuid::new_v4()));
+
+    let header = header_lines(header);
+    let mut content = String::new();
+    for line in &header {
+        content.push_str(line);
+        content.push('\n');
+    }
+    content.push('\n');
+    content.push_str(body);
+    fs::write(&path, content).context("Failed to write comment file")?;
+
+    let result = run_editor(&path)
+        .and_then(|_| fs::read_to_string(&path).context("Failed to read back comment file"));
+    let _ = fs::remove_file(&path);
+
+    Ok(strip_header(&result?, &header))
+}
+
+/// $VISUAL, then $EDITOR, then vi; the command may carry arguments such as
+/// "code --wait"
+fn run_editor(path: &std::path::Path) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+
+    Ok(())
+}
+
+/// The header as the `#` comment lines written to the file
+fn header_lines(header: &[String]) -> Vec<String> {
+    header
+        .iter()
+        .map(|line| {
+            if line.is_empty() {
+                "#".to_string()
+            } else {
+                format!("# {}", line)
+            }
+        })
+        .collect()
+}
+
+/// Drop the header lines `compose` wrote and surrounding blank lines
+///
+/// Only those exact lines go, in order and with any the user deleted
+/// skipped, so a body starting with a Markdown `#` heading is kept.
+fn strip_header(content: &str, header: &[String]) -> String {
+    let mut expected = header.iter();
+    let body: Vec<&str> = content
+        .lines()
+        .skip_while(|line| expected.any(|h| h == line))
+        .collect();
+
+    body.join("\n")
+        .trim_start_matches('\n')
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_written_header_is_stripped() {
+        let header = header_lines(&["Comment on src/lib.rs:3".to_string(), String::new()]);
+        assert_eq!(
+            strip_header("# Comment on src/lib.rs:3\n#\n\nText\n", &header),
+            "Text"
+        );
+        // A heading right below the header, with the blank line deleted
+        assert_eq!(
+            strip_header("# Comment on src/lib.rs:3\n#\n# Heading\nText\n", &header),
+            "# Heading\nText"
+        );
+        // Header lines the user deleted are skipped over
+        assert_eq!(strip_header("#\n# Heading\n", &header), "# Heading");
+    }
+}
@@ -0,0 +1,198 @@
+use crate::comment::COMMENT_FILE;
+use crate::export::Format;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookEvent {
+    Save,
+    Quit,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Save => "on_save",
+            HookEvent::Quit => "on_quit",
+        }
+    }
+}
+
+/// What the hook is told about the review, through VR_* environment
+/// variables
+#[derive(Debug, Clone)]
+pub struct HookEnv {
+    pub output_file: String,
+    pub format: Format,
+    pub comment_count: usize,
+}
+
+/// How a hook command finished
+#[derive(Debug, Clone)]
+pub struct HookResult {
+    pub event: HookEvent,
+    pub command: String,
+    pub status: Result<i32, String>, // Exit code, or why it couldn't run
+    pub output: String,              // Captured stdout and stderr
+}
+
+impl HookResult {
+    pub fn success(&self) -> bool {
+        self.status == Ok(0)
+    }
+
+    /// e.g. "on_save hook exited with 0: Review sent"
+    pub fn summary(&self) -> String {
+        let status = match &self.status {
+            Ok(code) => format!("{} hook exited with {}", self.event.name(), code),
+            Err(e) => format!("{} hook failed: {}", self.event.name(), e),
+        };
+        match self.output.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(last) => format!("{}: {}", status, last.trim()),
+            None => status,
+        }
+    }
+}
+
+/// Run a hook in the background, capturing its output
+pub fn spawn(event: HookEvent, command: String, env: HookEnv, stdin: bool) -> Receiver<HookResult> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(run(event, command, &env, stdin, true));
+    });
+    rx
+}
+
+/// Run a hook and wait for it; without `capture` its output goes straight
+/// to the terminal
+pub fn run(
+    event: HookEvent,
+    command: String,
+    env: &HookEnv,
+    stdin: bool,
+    capture: bool,
+) -> HookResult {
+    let (status, output) = match execute(event, &command, env, stdin, capture) {
+        Ok((code, output)) => (Ok(code), output),
+        Err(e) => (Err(format!("{:#}", e)), String::new()),
+    };
+    HookResult {
+        event,
+        command,
+        status,
+        output,
+    }
+}
+
+fn execute(
+    event: HookEvent,
+    command: &str,
+    env: &HookEnv,
+    stdin: bool,
+    capture: bool,
+) -> Result<(i32, String)> {
+    let mut cmd = shell(command);
+    cmd.env("VR_EVENT", event.name())
+        .env("VR_OUTPUT", &env.output_file)
+        .env("VR_FORMAT", env.format.name())
+        .env("VR_SESSION", COMMENT_FILE)
+        .env("VR_COMMENT_COUNT", env.comment_count.to_string())
+        .stdin(if stdin { Stdio::piped() } else { Stdio::null() });
+    if capture {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'", command))?;
+
+    // The comments are written from another thread while the output is
+    // read, or a hook that writes before reading all its input would block
+    // on a full pipe. The output file may not exist yet if nothing was ever
+    // saved.
+    let writer = child.stdin.take().map(|mut pipe| {
+        let comments = fs::read(&env.output_file).unwrap_or_default();
+        thread::spawn(move || pipe.write_all(&comments))
+    });
+
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        match writer.join() {
+            // A hook that doesn't read its input closes the pipe early
+            Ok(Err(e)) if e.kind() == ErrorKind::BrokenPipe => {}
+            Ok(result) => result.context("Failed to write the comments to the hook")?,
+            Err(_) => anyhow::bail!("Failed to write the comments to the hook"),
+        }
+    }
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    // Killed by a signal has no exit code
+    Ok((output.status.code().unwrap_or(-1), text))
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn env(output_file: &std::path::Path) -> HookEnv {
+        HookEnv {
+            output_file: output_file.display().to_string(),
+            format: Format::Json,
+            comment_count: 3,
+        }
+    }
+
+    #[test]
+    fn hooks_get_the_review_in_env_vars_and_stdin() {
+        let output = std::env::temp_dir().join(format!("vr-hook-test-{}.json", Uuid::new_v4()));
+        fs::write(&output, "[1, 2, 3]\n").unwrap();
+
+        let command = "echo \"$VR_EVENT $VR_FORMAT $VR_COMMENT_COUNT $VR_SESSION $VR_OUTPUT\"; cat";
+        let result = spawn(HookEvent::Save, command.into(), env(&output), true)
+            .recv()
+            .unwrap();
+        assert!(result.success());
+        assert_eq!(
+            result.output,
+            format!(
+                "on_save json 3 {} {}\n[1, 2, 3]\n",
+                COMMENT_FILE,
+                output.display()
+            )
+        );
+
+        // Without stdin the hook reads nothing
+        let result = spawn(HookEvent::Quit, "cat; exit 2".into(), env(&output), false)
+            .recv()
+            .unwrap();
+        assert_eq!((&result.status, result.output.as_str()), (&Ok(2), ""));
+        assert_eq!(result.summary(), "on_quit hook exited with 2");
+
+        // More than a pipe holds, to a hook that writes while it reads
+        let big = "x".repeat(1 << 20);
+        fs::write(&output, &big).unwrap();
+        let result = spawn(HookEvent::Save, "cat".into(), env(&output), true)
+            .recv()
+            .unwrap();
+        fs::remove_file(&output).unwrap();
+        assert_eq!(result.output.len(), big.len());
+    }
+}
@@ -0,0 +1,441 @@
+use crate::comment::{self, Category, Comment, CommentStatus, Severity, Suggestion};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Read findings from a SARIF log, reviewdog rdjson/rdjsonl, a quickfix
+/// list or vr's own JSON/JSONL export
+///
+/// The format is detected from the content. Findings come back as comments
+/// without context; the caller anchors them on the diff. Paths are made
+/// relative to `root`.
+pub fn load(path: &Path, root: &Path) -> Result<Vec<Comment>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut comments = match serde_json::from_str::<Value>(&content) {
+        Ok(doc) if doc.get("runs").is_some() => from_sarif(&doc),
+        Ok(doc) if doc.get("diagnostics").is_some() => doc["diagnostics"]
+            .as_array()
+            .map(|d| d.iter().filter_map(from_rdjson).collect())
+            .unwrap_or_default(),
+        Ok(doc) if doc.get("comments").is_some() => doc["comments"]
+            .as_array()
+            .map(|c| c.iter().filter_map(from_vr_json).collect())
+            .unwrap_or_default(),
+        Ok(doc) => from_json_lines(&[doc]),
+        Err(_) => match content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str::<Value>)
+            .collect::<std::result::Result<Vec<_>, _>>()
+        {
+            Ok(records) => from_json_lines(&records),
+            Err(_) => from_quickfix(&content),
+        },
+    };
+
+    for c in &mut comments {
+        c.file_path = relative_path(&c.file_path, root);
+    }
+    Ok(comments)
+}
+
+/// JSON Lines records: vr's own (with a schema version) or reviewdog's
+fn from_json_lines(records: &[Value]) -> Vec<Comment> {
+    records
+        .iter()
+        .filter_map(|r| {
+            if r.get("schema_version").is_some() {
+                from_vr_json(r)
+            } else {
+                from_rdjson(r)
+            }
+        })
+        .collect()
+}
+
+/// A comment from vr's JSON export, keeping its id, thread and labels
+fn from_vr_json(c: &Value) -> Option<Comment> {
+    let mut comment = Comment::new(
+        c["file"].as_str()?.to_string(),
+        line(&c["line"]["start"])?,
+        c["text"].as_str().unwrap_or_default().to_string(),
+        Vec::new(),
+    );
+    if let Some(id) = c["id"].as_str().and_then(|id| Uuid::parse_str(id).ok()) {
+        comment.id = id;
+    }
+    comment.in_reply_to = c["in_reply_to"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok());
+    comment.author = c["author"].as_str().map(str::to_string);
+    comment.status = serde_json::from_value(c["status"].clone()).unwrap_or_default();
+    comment.severity = c["severity"].as_str().and_then(Severity::parse);
+    comment.category = c["category"].as_str().and_then(Category::parse);
+    comment.suggestion = serde_json::from_value(c["suggestion"].clone()).ok();
+    Some(comment)
+}
+
+/// One comment per SARIF result, signed with the tool's name
+fn from_sarif(log: &Value) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    for run in log["runs"].as_array().into_iter().flatten() {
+        let tool = run["tool"]["driver"]["name"].as_str();
+
+        for result in run["results"].as_array().into_iter().flatten() {
+            let location = &result["locations"][0]["physicalLocation"];
+            let (Some(uri), Some(start)) = (
+                location["artifactLocation"]["uri"].as_str(),
+                line(&location["region"]["startLine"]),
+            ) else {
+                continue;
+            };
+
+            let mut text = result["message"]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            // vr's own rule ids only repeat the category
+            if let Some(rule) = result["ruleId"]
+                .as_str()
+                .filter(|r| !r.starts_with("review/"))
+            {
+                text = format!("{} ({})", text, rule);
+            }
+
+            let mut comment = Comment::new(uri.to_string(), start, text, Vec::new());
+            // The author and labels written by vr's SARIF export win over
+            // the tool name and level
+            let properties = &result["properties"];
+            comment.author = properties["author"].as_str().or(tool).map(str::to_string);
+            comment.severity = properties["severity"]
+                .as_str()
+                .and_then(Severity::parse)
+                .or(match result["level"].as_str() {
+                    Some("error") => Some(Severity::Blocker),
+                    Some("note") => Some(Severity::Suggestion),
+                    Some("none") => Some(Severity::Nit),
+                    _ => Some(Severity::Issue),
+                });
+            comment.category = properties["category"].as_str().and_then(Category::parse);
+            if let (text, Some(replacement)) = comment::split_suggestion(&comment.text) {
+                comment.text = text;
+                comment.suggestion = Some(Suggestion {
+                    end_line: line(&location["region"]["endLine"]).unwrap_or(start),
+                    original: Vec::new(),
+                    replacement,
+                });
+            }
+            comments.push(comment);
+        }
+    }
+    comments
+}
+
+/// A reviewdog Diagnostic; its first suggestion becomes a suggested change
+fn from_rdjson(d: &Value) -> Option<Comment> {
+    let range = &d["location"]["range"];
+    let start = line(&range["start"]["line"])?;
+
+    let mut text = d["message"].as_str().unwrap_or_default().to_string();
+    if let Some(code) = d["code"]["value"]
+        .as_str()
+        .filter(|c| !c.starts_with("review/"))
+    {
+        text = format!("{} ({})", text, code);
+    }
+
+    let mut comment = Comment::new(
+        d["location"]["path"].as_str()?.to_string(),
+        start,
+        text,
+        Vec::new(),
+    );
+    comment.author = d["source"]["name"].as_str().map(str::to_string);
+    // vr's own messages start with the author and bold label tags, such as
+    // **alice:** **[nit]**
+    let mut rest = comment.text.as_str();
+    if let Some((name, after)) = rest
+        .strip_prefix("**")
+        .and_then(|r| r.split_once(":** "))
+        .filter(|(name, _)| !name.starts_with('['))
+    {
+        comment.author = Some(name.to_string());
+        rest = after;
+    }
+    let mut tags = String::new();
+    while let Some((label, after)) = rest.strip_prefix("**[").and_then(|r| r.split_once("]**")) {
+        tags.push_str(&format!("[{}]", label));
+        rest = after.trim_start();
+    }
+    comment.text = format!("{}{}", tags, rest);
+    comment.apply_label_tags();
+    comment.severity = comment.severity.or(match d["severity"].as_str() {
+        Some("ERROR") => Some(Severity::Blocker),
+        Some("WARNING") => Some(Severity::Issue),
+        Some("INFO") => Some(Severity::Suggestion),
+        _ => None,
+    });
+    comment.category = comment.category.or(d["code"]["value"]
+        .as_str()
+        .and_then(|c| c.strip_prefix("review/"))
+        .and_then(Category::parse));
+
+    // Whole-line suggestions end at column 1 of the line after the last
+    // one; the replaced lines are filled in from the diff when anchoring
+    if let Some(s) = d["suggestions"].as_array().and_then(|s| s.first()) {
+        let s_start = line(&s["range"]["start"]["line"]).unwrap_or(start);
+        let s_end = line(&s["range"]["end"]["line"]).unwrap_or(s_start);
+        let end_column = s["range"]["end"]["column"].as_u64().unwrap_or(1);
+        let end_line = if end_column <= 1 && s_end > s_start {
+            s_end - 1
+        } else {
+            s_end
+        };
+        let replacement = s["text"].as_str().unwrap_or_default();
+        comment.line_number = s_start;
+        comment.suggestion = Some(Suggestion {
+            end_line,
+            original: Vec::new(),
+            replacement: replacement
+                .strip_suffix('\n')
+                .unwrap_or(replacement)
+                .to_string(),
+        });
+    }
+
+    Some(comment)
+}
+
+/// `path:line[:col]: text` entries, with further lines of the text indented
+/// by four spaces as vr writes them; other lines are ignored
+///
+/// Indented `↳ author: text` lines are replies, as vr writes them, the
+/// `[L10-L14]` tag of a suggestion gives the lines it replaces and an
+/// `[@name]` tag the author.
+fn from_quickfix(content: &str) -> Vec<Comment> {
+    let mut comments: Vec<Comment> = Vec::new();
+    let mut root: Option<usize> = None; // Thread the indented lines belong to
+    let mut end_lines: HashMap<Uuid, usize> = HashMap::new();
+
+    for entry in content.lines() {
+        if let Some(more) = entry.strip_prefix("    ") {
+            let Some(root) = root else {
+                continue;
+            };
+            if let Some(reply) = more.strip_prefix("↳ ") {
+                let (author, text) = reply.split_once(": ").unwrap_or(("reply", reply));
+                let mut reply = comments[root].reply(text.to_string());
+                reply.author = (author != "reply").then(|| author.to_string());
+                comments.push(reply);
+                continue;
+            }
+            let last = comments.last_mut().expect("root is an index into comments");
+            let more = match last.in_reply_to {
+                Some(_) => more.strip_prefix("  ").unwrap_or(more),
+                None => more,
+            };
+            last.text.push('\n');
+            last.text.push_str(more);
+            continue;
+        }
+
+        let mut parts = entry.splitn(4, ':');
+        let (Some(path), Some(line_no)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(line_no) = line_no.trim().parse::<usize>() else {
+            continue;
+        };
+        // The column is optional
+        let rest: Vec<&str> = parts.collect();
+        let text = match rest.as_slice() {
+            [col, text] if col.trim().parse::<usize>().is_ok() => text.to_string(),
+            _ => rest.join(":"),
+        };
+
+        let mut comment = Comment::new(
+            path.to_string(),
+            line_no,
+            text.trim().to_string(),
+            Vec::new(),
+        );
+        comment.apply_label_tags();
+        // A status tag from vr's own quickfix export follows the labels
+        for status in [CommentStatus::Resolved, CommentStatus::WontFix] {
+            let tag = format!("[{}]", status.label());
+            if let Some(rest) = comment.text.strip_prefix(&tag) {
+                comment.text = rest.trim_start().to_string();
+                comment.status = status;
+            }
+        }
+        let range = comment
+            .text
+            .strip_prefix(&format!("[L{}-L", line_no))
+            .and_then(|r| r.split_once(']'))
+            .and_then(|(end, rest)| Some((end.parse::<usize>().ok()?, rest)));
+        if let Some((end_line, rest)) = range {
+            end_lines.insert(comment.id, end_line);
+            comment.text = rest.trim_start().to_string();
+        }
+        if let Some((name, rest)) = comment
+            .text
+            .strip_prefix("[@")
+            .and_then(|r| r.split_once(']'))
+        {
+            comment.author = Some(name.to_string());
+            comment.text = rest.trim_start().to_string();
+        }
+        root = Some(comments.len());
+        comments.push(comment);
+    }
+
+    // vr writes a suggested change as a ```suggestion block in the text
+    for c in &mut comments {
+        if let (text, Some(replacement)) = comment::split_suggestion(&c.text) {
+            c.text = text;
+            c.suggestion = Some(Suggestion {
+                end_line: end_lines.get(&c.id).copied().unwrap_or(c.line_number),
+                original: Vec::new(),
+                replacement,
+            });
+        }
+    }
+    comments
+}
+
+fn line(value: &Value) -> Option<usize> {
+    value.as_u64().map(|n| n as usize).filter(|&n| n > 0)
+}
+
+/// Turn `file://` URIs and absolute paths inside the repository into paths
+/// relative to its root, like the ones in the diff
+fn relative_path(path: &str, root: &Path) -> String {
+    let path = path.strip_prefix("file://").unwrap_or(path);
+    let path = Path::new(path);
+    let path = path.strip_prefix(root).unwrap_or(path);
+    let path = path.to_string_lossy();
+    path.strip_prefix("./").unwrap_or(&path).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quickfix_ranges_and_replies() {
+        let comments = from_quickfix(include_str!("../tests/fixtures/review.qf"));
+        assert_eq!(comments.len(), 4);
+
+        let root = &comments[0];
+        assert_eq!(
+            (root.file_path.as_str(), root.line_number),
+            ("src/lib.rs", 10)
+        );
+        assert_eq!(root.severity, Some(Severity::Nit));
+        assert_eq!(root.author.as_deref(), Some("bob"));
+        assert_eq!(root.text, "Simplify this");
+        let suggestion = root.suggestion.as_ref().unwrap();
+        assert_eq!(
+            (suggestion.end_line, suggestion.replacement.as_str()),
+            (12, "let x = 1;")
+        );
+
+        let replies: Vec<(Option<&str>, &str)> = comments[1..3]
+            .iter()
+            .inspect(|r| assert_eq!(r.in_reply_to, Some(root.id)))
+            .map(|r| (r.author.as_deref(), r.text.as_str()))
+            .collect();
+        assert_eq!(
+            replies,
+            [(Some("alice"), "Agreed\non both counts"), (None, "Done")]
+        );
+
+        let resolved = &comments[3];
+        assert_eq!(resolved.status, CommentStatus::Resolved);
+        assert_eq!(resolved.category, Some(Category::Security));
+        assert_eq!(resolved.text, "Why?");
+    }
+
+    #[test]
+    fn quickfix_from_other_tools() {
+        let comments = from_quickfix("a.c:3: warning: unused\nnot an entry\nb.c:7:2: error\n");
+        let entries: Vec<(&str, usize, &str)> = comments
+            .iter()
+            .map(|c| (c.file_path.as_str(), c.line_number, c.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [("a.c", 3, "warning: unused"), ("b.c", 7, "error")]
+        );
+    }
+
+    #[test]
+    fn sarif_from_other_tools() {
+        let log = serde_json::json!({ "runs": [{
+            "tool": { "driver": { "name": "clippy" } },
+            "results": [
+                {
+                    "ruleId": "clippy::unwrap_used",
+                    "level": "error",
+                    "message": { "text": "used unwrap()" },
+                    "locations": [{ "physicalLocation": {
+                        "artifactLocation": { "uri": "file:///repo/src/main.rs" },
+                        "region": { "startLine": 4 },
+                    }}],
+                },
+                {
+                    "message": { "text": "no location" },
+                    "locations": [],
+                },
+            ],
+        }]});
+        let comments = from_sarif(&log);
+        assert_eq!(comments.len(), 1);
+        let c = &comments[0];
+        assert_eq!(c.text, "used unwrap() (clippy::unwrap_used)");
+        assert_eq!(c.author.as_deref(), Some("clippy"));
+        assert_eq!(c.severity, Some(Severity::Blocker));
+        assert_eq!(c.line_number, 4);
+        assert_eq!(
+            relative_path(&c.file_path, Path::new("/repo")),
+            "src/main.rs"
+        );
+    }
+
+    #[test]
+    fn rdjson_labels_author_and_suggestion() {
+        let fixture = include_str!("../tests/fixtures/review.rdjsonl");
+        let records: Vec<Value> = fixture
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let comments = from_json_lines(&records);
+
+        let nit = &comments[1];
+        assert_eq!(nit.author.as_deref(), Some("Ann"));
+        assert_eq!(
+            (nit.severity, nit.category),
+            (Some(Severity::Nit), Some(Category::Style))
+        );
+        assert_eq!(nit.text, "Inline it\n\n> **reply:** Agreed");
+        let suggestion = nit.suggestion.as_ref().unwrap();
+        assert_eq!((nit.line_number, suggestion.end_line), (2, 4));
+        assert_eq!(suggestion.replacement, "fn b() -> u8 { 1 }");
+
+        // reviewdog's own severities apply when vr's labels are missing
+        let plain = from_rdjson(&serde_json::json!({
+            "message": "m",
+            "severity": "WARNING",
+            "source": { "name": "golangci" },
+            "location": { "path": "a.go", "range": { "start": { "line": 3 } } },
+        }))
+        .unwrap();
+        assert_eq!(plain.severity, Some(Severity::Issue));
+        assert_eq!(plain.author.as_deref(), Some("golangci"));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::comment::{self, Comment};
+use crate::diff::{self, DiffLine, LineType};
+use crate::session::ReviewedRevisions;
+use crate::vcs::{self, git, VcsType};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use uuid::Uuid;
+
+/// Lines of unchanged code shown on each side of an old comment
+const COMMENT_CONTEXT: usize = 3;
+
+/// One version of a change: the commit it was based on and its last commit
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub base: String,
+    pub head: String,
+}
+
+impl Version {
+    /// The version a ref reviews, resolved as `vr <ref>` would diff it
+    pub fn resolve(vcs: VcsType, vcs_ref: &str) -> Result<Self> {
+        match vcs.revisions(Some(vcs_ref)) {
+            (Some(base), Some(head)) => Ok(Self { base, head }),
+            _ => anyhow::bail!("Can't resolve {} to a base and head commit", vcs_ref),
+        }
+    }
+
+    /// The version the session's comments were last saved against
+    pub fn reviewed() -> Result<Self> {
+        match ReviewedRevisions::load()? {
+            ReviewedRevisions {
+                base_sha: Some(base),
+                head_sha: Some(head),
+            } => Ok(Self { base, head }),
+            _ => anyhow::bail!(
+                "This session has no reviewed commit; pass the old version: vr --interdiff <old-ref> <new-ref>"
+            ),
+        }
+    }
+}
+
+/// What changed between two versions of a change
+pub struct Interdiff {
+    pub diff_lines: Vec<DiffLine>,
+    pub note: Option<String>, // Why the result isn't a clean interdiff
+}
+
+/// Compare the patches of two versions rather than their trees
+///
+/// The old version's patch is applied to the new version's base, so that
+/// changes which only came in with a rebase drop out, and the result is
+/// diffed with the new version. Comments written on the old version are
+/// moved onto the new one, and comments on code that didn't change get a
+/// hunk of unchanged lines so they still show in place. Comments that
+/// weren't on the old version are from other reviews and are left out.
+pub fn load(
+    vcs: VcsType,
+    root: &Path,
+    old: &Version,
+    new: &Version,
+    comments: &mut Vec<Comment>,
+) -> Result<Interdiff> {
+    if vcs != VcsType::Git {
+        anyhow::bail!("--interdiff needs a git repository");
+    }
+
+    let old_diff = diff::parse_diff(&git(root, &["diff", &old.base, &old.head])?)?;
+    comments.retain(|c| comment::in_diff(c, &old_diff));
+
+    let (from, note) = if old.base == new.base {
+        (old.head.clone(), None)
+    } else {
+        match rebase_tree(root, old, &new.base) {
+            Ok(tree) => (tree, None),
+            Err(e) => (
+                old.head.clone(),
+                Some(format!(
+                    "Old version doesn't apply to the new base ({:#}); showing everything that differs",
+                    e
+                )),
+            ),
+        }
+    };
+
+    let diff_lines = diff::parse_diff(&git(root, &["diff", &from, &new.head])?)?;
+
+    // Comment lines are lines of the old version, which shift with the
+    // rebase before they shift with the interdiff
+    let rebase = if from == old.head {
+        Vec::new()
+    } else {
+        diff::parse_diff(&git(root, &["diff", &old.head, &from])?)?
+    };
+    let expected: Vec<usize> = comments
+        .iter()
+        .map(|c| {
+            let line = diff::map_line(&[], &rebase, &c.file_path, c.line_number);
+            diff::map_line(&[], &diff_lines, &c.file_path, line)
+        })
+        .collect();
+    comment::reanchor(comments, &diff_lines, &expected);
+
+    Ok(Interdiff {
+        diff_lines: show_comments(root, diff_lines, comments, &new.head),
+        note,
+    })
+}
+
+/// Tree of the old version's patch applied on top of `base`
+fn rebase_tree(root: &Path, old: &Version, base: &str) -> Result<String> {
+    let patch = git(root, &["diff", "--binary", &old.base, &old.head])?;
+    if patch.is_empty() {
+        return git_object(root, &["rev-parse", &format!("{}^{{tree}}", base)], None);
+    }
+
+    // A throwaway index, so neither the real one nor the working tree is
+    // touched
+    let index = std::env::temp_dir().join(format!("vr-interdiff-{}.index", Uuid::new_v4()));
+    let index_path = index.to_string_lossy().to_string();
+    let result = (|| {
+        git_object(root, &["read-tree", base], Some(&index_path))?;
+        apply_cached(root, &patch, &index_path)?;
+        git_object(root, &["write-tree"], Some(&index_path))
+    })();
+    let _ = fs::remove_file(&index);
+    result
+}
+
+/// git apply --cached --3way, feeding the patch on stdin
+fn apply_cached(root: &Path, patch: &str, index: &str) -> Result<()> {
+    let mut child = vcs::git_command(root, &["apply", "--cached", "--3way", "-"])
+        .env("GIT_INDEX_FILE", index)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git apply")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(patch.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}", error.lines().last().unwrap_or("git apply failed"));
+    }
+    Ok(())
+}
+
+/// Name of the object a git command prints, such as a tree's SHA,
+/// optionally run against another index
+fn git_object(root: &Path, args: &[&str], index: Option<&str>) -> Result<String> {
+    let mut command = vcs::git_command(root, args);
+    if let Some(index) = index {
+        command.env("GIT_INDEX_FILE", index);
+    }
+    Ok(vcs::run_git(&mut command)?.trim_end().to_string())
+}
+
+/// Add hunks of unchanged lines around comments the diff doesn't reach
+fn show_comments(
+    root: &Path,
+    diff_lines: Vec<DiffLine>,
+    comments: &[Comment],
+    head: &str,
+) -> Vec<DiffLine> {
+    let mut wanted: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for c in comments {
+        if !comment::in_diff(c, &diff_lines) {
+            wanted.entry(&c.file_path).or_default().push(c.line_number);
+        }
+    }
+    if wanted.is_empty() {
+        return diff_lines;
+    }
+
+    // Split the diff into files, each a header and its hunks
+    let mut files: Vec<(String, Vec<DiffLine>, Vec<Vec<DiffLine>>)> = Vec::new();
+    for line in diff_lines {
+        if line.line_type == LineType::FileHeader && line.content.starts_with("diff ") {
+            files.push((line.file_path.clone(), Vec::new(), Vec::new()));
+        }
+        let Some((_, header, hunks)) = files.last_mut() else {
+            continue;
+        };
+        match line.line_type {
+            LineType::FileHeader => header.push(line),
+            LineType::HunkHeader => hunks.push(vec![line]),
+            _ => {
+                if let Some(hunk) = hunks.last_mut() {
+                    hunk.push(line);
+                }
+            }
+        }
+    }
+
+    for (path, lines) in wanted {
+        // Files deleted in the new version have nothing to show
+        let Ok(content) = git(root, &["show", &format!("{}:{}", head, path)]) else {
+            continue;
+        };
+        let content: Vec<&str> = content.lines().collect();
+
+        let idx = match files.iter().position(|(p, _, _)| p == path) {
+            Some(idx) => idx,
+            None => {
+                let header = [
+                    format!("diff --git a/{} b/{}", path, path),
+                    format!("--- a/{}", path),
+                    format!("+++ b/{}", path),
+                ]
+                .into_iter()
+                .map(|content| DiffLine {
+                    content,
+                    line_type: LineType::FileHeader,
+                    old_line_no: None,
+                    new_line_no: None,
+                    file_path: path.to_string(),
+                    commit: None,
+                })
+                .collect();
+                let at = files
+                    .iter()
+                    .position(|(p, _, _)| p.as_str() > path)
+                    .unwrap_or(files.len());
+                files.insert(at, (path.to_string(), header, Vec::new()));
+                at
+            }
+        };
+
+        let file_diff: Vec<DiffLine> = files[idx].2.iter().flatten().cloned().collect();
+        let in_diff = |n: usize| file_diff.iter().any(|l| l.new_line_no == Some(n));
+
+        // Runs of lines around the comments that no hunk covers yet
+        let mut shown: Vec<usize> = lines
+            .iter()
+            .flat_map(|&line| {
+                line.saturating_sub(COMMENT_CONTEXT).max(1)
+                    ..=(line + COMMENT_CONTEXT).min(content.len())
+            })
+            .filter(|&n| !in_diff(n))
+            .collect();
+        shown.sort_unstable();
+        shown.dedup();
+
+        let mut runs: Vec<Vec<usize>> = Vec::new();
+        for n in shown {
+            match runs.last_mut() {
+                Some(run) if run.last() == Some(&(n - 1)) => run.push(n),
+                _ => runs.push(vec![n]),
+            }
+        }
+
+        for run in runs {
+            let old_start = diff::map_line(&file_diff, &[], path, run[0]);
+            let mut hunk = vec![DiffLine {
+                content: format!(
+                    "@@ -{},{} +{},{} @@ unchanged",
+                    old_start,
+                    run.len(),
+                    run[0],
+                    run.len()
+                ),
+                line_type: LineType::HunkHeader,
+                old_line_no: None,
+                new_line_no: None,
+                file_path: path.to_string(),
+                commit: None,
+            }];
+            hunk.extend(run.iter().enumerate().map(|(i, &n)| DiffLine {
+                content: format!(" {}", content[n - 1]),
+                line_type: LineType::Context,
+                old_line_no: Some(old_start + i),
+                new_line_no: Some(n),
+                file_path: path.to_string(),
+                commit: None,
+            }));
+            files[idx].2.push(hunk);
+        }
+
+        files[idx]
+            .2
+            .sort_by_key(|hunk| hunk.iter().find_map(|l| l.new_line_no).unwrap_or(0));
+    }
+
+    files
+        .into_iter()
+        .flat_map(|(_, header, hunks)| header.into_iter().chain(hunks.into_iter().flatten()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::testing::TempRepo;
+
+    /// Commit `f` with the given lines, returning the commit's SHA
+    fn commit(repo: &TempRepo, lines: &[String]) -> String {
+        repo.commit("f", &(lines.join("\n") + "\n"), "change")
+    }
+
+    #[test]
+    fn rebased_change_keeps_old_comments_in_place() {
+        let repo = TempRepo::new("interdiff-test");
+        let root = repo.path();
+        let mut lines: Vec<String> = (1..=30).map(|n| format!("l{}", n)).collect();
+        let base = commit(&repo, &lines);
+
+        // The old version changes lines 5 and 20
+        lines[4] = "c5".into();
+        lines[19] = "c20".into();
+        let old = Version {
+            base: base.clone(),
+            head: commit(&repo, &lines),
+        };
+
+        // Meanwhile two lines are added on top, and the new version is
+        // rebased onto that with line 5 changed again
+        repo.run(&["checkout", "-q", &base]);
+        let mut rebased: Vec<String> = ["n1", "n2"].map(String::from).to_vec();
+        rebased.extend((1..=30).map(|n| format!("l{}", n)));
+        let new_base = commit(&repo, &rebased);
+        rebased[6] = "d5".into();
+        rebased[21] = "c20".into();
+        let new = Version {
+            base: new_base.clone(),
+            head: commit(&repo, &rebased),
+        };
+
+        // The old patch applies cleanly to the new base
+        let tree = rebase_tree(root, &old, &new_base).unwrap();
+        let f = repo.run(&["show", &format!("{}:f", tree)]);
+        assert_eq!(f.lines().nth(6), Some("c5"));
+        assert_eq!(f.lines().nth(21), Some("c20"));
+
+        let stored = vec![
+            Comment::new("f".into(), 20, "Why c20?".into(), Vec::new()),
+            Comment::new("f".into(), 12, "Not on the old version".into(), Vec::new()),
+        ];
+        let mut comments = stored.clone();
+        let result = load(VcsType::Git, root, &old, &new, &mut comments).unwrap();
+
+        assert!(result.note.is_none());
+        // Moved by the rebase, then through the interdiff
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].line_number, 22);
+
+        // Saving keeps the move, and the other review's comment
+        let loaded = comment::stored_versions(&comments, &stored);
+        let (session, review) = comment::merge_review(&comments, &loaded, stored);
+        assert_eq!(review[0].line_number, 22);
+        let lines: Vec<usize> = session.iter().map(|c| c.line_number).collect();
+        assert_eq!(lines, [12, 22]);
+
+        let changes: Vec<&str> = result
+            .diff_lines
+            .iter()
+            .filter(|l| matches!(l.line_type, LineType::Added | LineType::Removed))
+            .map(|l| l.content.as_str())
+            .collect();
+        assert_eq!(changes, ["-c5", "+d5"]);
+
+        // The comment's line isn't part of the interdiff, so unchanged
+        // lines around it are shown
+        let header = result
+            .diff_lines
+            .iter()
+            .position(|l| l.content.ends_with("@@ unchanged"))
+            .unwrap();
+        assert_eq!(
+            result.diff_lines[header].content,
+            "@@ -19,7 +19,7 @@ unchanged"
+        );
+        let commented = result
+            .diff_lines
+            .iter()
+            .find(|l| l.new_line_no == Some(22))
+            .unwrap();
+        assert_eq!(
+            (commented.content.as_str(), &commented.line_type),
+            (" c20", &LineType::Context)
+        );
+    }
+}
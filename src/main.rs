@@ -1,60 +1,244 @@
 mod app;
+mod cli;
 mod comment;
+mod config;
 mod diff;
+mod editor;
+mod export;
+mod external_editor;
+mod hooks;
+mod import;
+mod interdiff;
+mod range_diff;
+mod serve;
+mod session;
+mod suggestion;
 mod tui;
 mod vcs;
 mod vim;
+mod watch;
 
 use anyhow::Result;
 use std::env;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let args = cli::parse(env::args().skip(1))?;
+
+    if let cli::Command::Apply(session) = &args.command {
+        return apply(session.as_deref());
+    }
+    if args.command == cli::Command::Quickfix {
+        return quickfix(&args.filter);
+    }
+    if args.command == cli::Command::Serve {
+        return serve::run(args.vcs_ref, args.author);
+    }
+
+    let config = config::Config::load()?;
+
+    // A broken template would only show up when the comments are saved
+    if let export::Format::Prompt(template) = &args.format {
+        export::check_template(template)?;
+    }
 
     // Auto-detect VCS type (git or mercurial)
     let vcs_type = vcs::VcsType::detect()?;
 
-    // Parse VCS ref argument (default to uncommitted changes)
-    let vcs_ref = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
+    // Load existing comments from the session (empty for new sessions)
+    let stored = comment::load_comments()?;
+    let mut comments = stored.clone();
 
-    // Get diff from VCS
-    let diff_text = vcs_type.get_diff(vcs_ref.as_deref())?;
+    // --interdiff shows what changed since an older version of the change,
+    // with the comments made on it moved onto the new one
+    let mut vcs_ref = args.vcs_ref;
+    let mut interdiff_note = None;
+    let (diff_text, diff_lines) = if args.interdiff {
+        let old = match &args.old_ref {
+            Some(old_ref) => interdiff::Version::resolve(vcs_type, old_ref)?,
+            None => interdiff::Version::reviewed()?,
+        };
+        // Without a new ref, the new version is HEAD on the same base
+        let new_ref = vcs_ref.get_or_insert_with(|| format!("{}..HEAD", old.base));
+        let new = interdiff::Version::resolve(vcs_type, new_ref)?;
+        let result = interdiff::load(vcs_type, &vcs::repo_root()?, &old, &new, &mut comments)?;
+        interdiff_note = result.note;
+        (String::new(), result.diff_lines)
+    } else if args.range_diff {
+        // --range-diff pairs up the commits of two versions of a series
+        let ranges: Vec<&str> = args
+            .old_ref
+            .iter()
+            .chain(&vcs_ref)
+            .map(String::as_str)
+            .collect();
+        (
+            String::new(),
+            range_diff::load(vcs_type, &vcs::repo_root()?, &ranges)?,
+        )
+    } else {
+        // Get diff from VCS (default to uncommitted changes)
+        let diff_text = vcs_type.get_diff(vcs_ref.as_deref())?;
 
-    if diff_text.is_empty() {
-        println!("No changes to review");
-        return Ok(());
-    }
+        if diff_text.is_empty() {
+            println!("No changes to review");
+            return Ok(());
+        }
 
-    // Parse diff into structured format
-    let diff_lines = diff::parse_diff(&diff_text)?;
+        // Parse diff into structured format
+        let diff_lines = diff::parse_diff(&diff_text)?;
+        (diff_text, diff_lines)
+    };
 
     if diff_lines.is_empty() {
         println!("No changes to review");
         return Ok(());
     }
 
+    // The session also keeps comments from reviews of other changes, which
+    // stay out of this one
+    comments.retain(|c| comment::in_diff(c, &diff_lines));
+
     // Generate UUID for output file
     let uuid = Uuid::new_v4();
-    let output_file = format!("/tmp/vr/{}.txt", uuid);
+    let output_file = format!("/tmp/vr/{}.{}", uuid, args.format.extension());
 
-    // Load existing comments (empty for new sessions)
-    let comments = Vec::new();
+    // Restore which files and hunks were already reviewed
+    let viewed = session::ViewedState::load().unwrap_or_default();
 
     // Create app state
-    let mut app = app::App::new(diff_lines, comments, output_file.clone());
+    let mut app = app::App::new(diff_lines, comments.clone(), viewed, output_file.clone());
+    app.loaded_comments = comment::stored_versions(&comments, &stored);
+
+    // Sign new comments with --author, or the identity from the VCS config
+    app.author = vcs_type.author(&vcs::repo_root()?, args.author);
+    app.export_filter = args.filter;
+    app.export_format = args.format;
+    let (base_sha, head_sha) = vcs_type.revisions(vcs_ref.as_deref());
+    // --watch keeps the diff current while files change under the TUI
+    if args.watch {
+        app.watcher = Some(watch::DiffWatcher::new(
+            &vcs::repo_root()?,
+            vcs_type,
+            vcs_ref.clone(),
+            diff_text,
+        )?);
+    }
+
+    app.review = export::ReviewInfo {
+        vcs: Some(vcs_type.name()),
+        vcs_ref,
+        base_sha,
+        head_sha,
+    };
+
+    // Hook commands from the command line win over the config file
+    app.hooks = config.hooks.overridden_by(args.on_save, args.on_quit);
+
+    app.message = interdiff_note;
+
+    // Findings from other tools start out pending, to be triaged in the TUI
+    if !args.imports.is_empty() {
+        let root = vcs::repo_root()?;
+        let (mut added, mut skipped) = (0, 0);
+        for path in &args.imports {
+            let (a, s) = app.import_comments(import::load(Path::new(path), &root)?);
+            added += a;
+            skipped += s;
+        }
+        app.message = Some(format!(
+            "Imported {} finding{} ({} outside the diff or already present); A accepts, X dismisses",
+            added,
+            if added == 1 { "" } else { "s" },
+            skipped
+        ));
+    }
 
     // Run TUI
     tui::run(&mut app)?;
 
-    // Print output file path if comments were added
-    if !app.comments.is_empty() {
+    // Print output file path if the comments were written
+    if Path::new(&output_file).exists() {
         println!("\nComments saved to: {}", output_file);
     }
 
+    // An on_save hook started by :wq may still be running
+    if let Some(result) = app.wait_for_hook() {
+        print!("{}", result.output);
+        println!("{}", result.summary());
+    }
+
+    // on_quit runs in the foreground, so its output goes to the terminal
+    if let Some(command) = app.hooks.on_quit.clone() {
+        let result = hooks::run(
+            hooks::HookEvent::Quit,
+            command,
+            &app.hook_env(),
+            app.hooks.stdin,
+            false,
+        );
+        if !result.success() {
+            eprintln!("{}", result.summary());
+        }
+    }
+
+    Ok(())
+}
+
+/// vr apply [session]: patch the working tree with a session's suggested
+/// changes and record which ones were applied
+fn apply(session: Option<&str>) -> Result<()> {
+    // A session is a .vim-review directory or the comments.json inside it
+    let path = match session {
+        Some(session) if Path::new(session).is_dir() => Path::new(session).join("comments.json"),
+        Some(session) => PathBuf::from(session),
+        None => PathBuf::from(comment::COMMENT_FILE),
+    };
+    if !path.exists() {
+        anyhow::bail!("No review session at {}", path.display());
+    }
+
+    let mut comments = comment::load_comments_from(&path)?;
+    let report = suggestion::apply_suggestions(&mut comments, &vcs::repo_root()?);
+    comment::save_comments_to(&comments, &path)?;
+
+    for conflict in &report.conflicts {
+        eprintln!("conflict: {}", conflict);
+    }
+    println!("{}", report.summary());
+
+    if !report.conflicts.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// vr --quickfix: write the session's comments as a quickfix list and print
+/// how to load it in vim
+fn quickfix(filter: &comment::CommentFilter) -> Result<()> {
+    let comments = filter.apply(&comment::load_comments()?);
+    if comments.is_empty() {
+        println!("No comments in this review session");
+        return Ok(());
+    }
+
+    let format = export::Format::Quickfix;
+    let output_file = format!("/tmp/vr/{}.{}", Uuid::new_v4(), format.extension());
+    export::write(
+        &format,
+        &comments,
+        &export::ReviewInfo::default(),
+        &[],
+        &output_file,
+    )?;
+
+    // Spaces in an option value must be escaped for :set
+    let errorformat = export::ERRORFORMAT.replace(' ', "\\ ");
+    println!("Quickfix list written to: {}", output_file);
+    println!("Load it from the repository root in vim with:");
+    println!("  :set errorformat={} | cfile {}", errorformat, output_file);
+    println!("or start vim on it with:");
+    println!("  vim -q {}", output_file);
     Ok(())
 }
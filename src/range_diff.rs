@@ -0,0 +1,369 @@
+use crate::diff::{self, DiffLine, LineType};
+use crate::vcs::{git, VcsType};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Sections of a commit pair that aren't part of the patch
+const NON_PATCH_SECTIONS: [&str; 2] = ["Metadata", "Commit message"];
+
+/// Run `git range-diff` on one `A...B` range or an old and a new range, and
+/// turn its output into lines the TUI can show
+///
+/// Every commit pair starts a file header, so file motions, folds and
+/// viewed marks work per pair. The diff-of-diffs lines keep both markers,
+/// and lines that exist in the new commit get its line numbers, so comments
+/// anchor to the new commit's code. Every line also records its pair's
+/// commit, which comments made on it keep, so they don't show on another
+/// pair or in a review of the file itself.
+pub fn load(vcs: VcsType, root: &Path, ranges: &[&str]) -> Result<Vec<DiffLine>> {
+    if vcs != VcsType::Git {
+        anyhow::bail!("--range-diff needs a git repository");
+    }
+
+    let mut args = vec!["range-diff", "--no-color"];
+    args.extend(ranges);
+    parse(&git(root, &args)?, |sha| {
+        diff::parse_diff(&git(root, &["show", sha])?)
+    })
+}
+
+/// One line of the pairing table, e.g. `1:  a7e454f ! 1:  77640ef subject`
+struct Pair<'a> {
+    old_sha: &'a str,
+    status: &'a str, // "=" same, "!" changed, "<" only old, ">" only new
+    new_sha: &'a str,
+    subject: String,
+}
+
+impl<'a> Pair<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let (old_no, old_sha, status, new_no, new_sha) = (
+            words.next()?,
+            words.next()?,
+            words.next()?,
+            words.next()?,
+            words.next()?,
+        );
+        let is_number = |no: &str| no.strip_suffix(':').is_some_and(|n| !n.is_empty());
+        if !is_number(old_no) || !is_number(new_no) || !matches!(status, "=" | "!" | "<" | ">") {
+            return None;
+        }
+        Some(Self {
+            old_sha,
+            status,
+            new_sha,
+            subject: words.collect::<Vec<_>>().join(" "),
+        })
+    }
+
+    /// The new commit, or the old one if it has no counterpart
+    fn sha(&self) -> &'a str {
+        if self.status == "<" {
+            self.old_sha
+        } else {
+            self.new_sha
+        }
+    }
+
+    /// Name shown in the status bar and used for viewed state
+    fn label(&self) -> String {
+        format!("{} {}", self.sha(), self.subject)
+    }
+}
+
+/// Parse range-diff output; `commit_diff` gives a commit's own diff, which
+/// the new side of each changed pair is matched against for line numbers
+fn parse(
+    output: &str,
+    commit_diff: impl Fn(&str) -> Result<Vec<DiffLine>>,
+) -> Result<Vec<DiffLine>> {
+    let mut lines = Vec::new();
+    let mut label = String::new();
+    let mut sha: Option<&str> = None; // Commit the pair's lines are in
+    let mut new_commit: Vec<DiffLine> = Vec::new();
+    let mut matched: HashMap<String, usize> = HashMap::new(); // Per file, how far into new_commit
+    let mut file: Option<String> = None; // File of the patch section we're in
+
+    for line in output.lines() {
+        let key = |file: &Option<String>| file.clone().unwrap_or_else(|| label.clone());
+
+        if let Some(pair) = Pair::parse(line) {
+            label = pair.label();
+            sha = Some(pair.sha());
+            new_commit = if pair.status == "!" {
+                commit_diff(pair.new_sha)?
+            } else {
+                Vec::new()
+            };
+            matched.clear();
+            file = None;
+            lines.push(DiffLine {
+                content: line.to_string(),
+                line_type: LineType::FileHeader,
+                old_line_no: None,
+                new_line_no: None,
+                file_path: label.clone(),
+                commit: sha.map(str::to_string),
+            });
+            continue;
+        }
+
+        // Everything under a pair is indented by four spaces
+        let body = line.strip_prefix("    ").unwrap_or(line.trim_start());
+
+        // Outer hunk headers name the section they're in: "@@ path: context"
+        if let Some(section) = body.strip_prefix("@@") {
+            let section = section.trim();
+            let name = section.split_once(": ").map_or(section, |(name, _)| name);
+            file =
+                (!name.is_empty() && !NON_PATCH_SECTIONS.contains(&name)).then(|| name.to_string());
+            lines.push(DiffLine {
+                content: body.to_string(),
+                line_type: LineType::HunkHeader,
+                old_line_no: None,
+                new_line_no: None,
+                file_path: key(&file),
+                commit: sha.map(str::to_string),
+            });
+            continue;
+        }
+
+        let mut chars = body.chars();
+        let line_type = match chars.next() {
+            Some('+') => LineType::Added,
+            Some('-') => LineType::Removed,
+            _ => LineType::Context,
+        };
+        let inner = chars.as_str();
+
+        // Inner file headers look like " ## path ##", " ## path (new) ##"
+        // or " ## old => new ##"; renames go by their old path, as in
+        // parse_diff
+        if let Some(name) = inner
+            .strip_prefix(" ## ")
+            .and_then(|name| name.strip_suffix(" ##"))
+        {
+            let name = name.split_once(" (").map_or(name, |(name, _)| name);
+            let name = name.split_once(" => ").map_or(name, |(old, _)| old);
+            file = (!NON_PATCH_SECTIONS.contains(&name)).then(|| name.to_string());
+        }
+
+        // Lines on the new side of the patch take their numbers from the new
+        // commit, found by walking its diff in order
+        let (mut old_line_no, mut new_line_no) = (None, None);
+        if let Some(path) = &file {
+            let is_patch_line =
+                matches!(inner.chars().next(), Some(' ' | '+' | '-')) && !inner.starts_with(" ## ");
+            if line_type != LineType::Removed && is_patch_line {
+                let from = matched.get(path).copied().unwrap_or(0);
+                let found = new_commit.iter().enumerate().skip(from).find(|(_, l)| {
+                    l.file_path == *path
+                        && l.line_type != LineType::HunkHeader
+                        && l.line_type != LineType::FileHeader
+                        && l.content == inner
+                });
+                if let Some((idx, l)) = found {
+                    matched.insert(path.clone(), idx + 1);
+                    (old_line_no, new_line_no) = (l.old_line_no, l.new_line_no);
+                }
+            }
+        }
+
+        lines.push(DiffLine {
+            content: body.to_string(),
+            line_type,
+            old_line_no,
+            new_line_no,
+            file_path: key(&file),
+            commit: sha.map(str::to_string),
+        });
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+    use crate::session::ViewedState;
+    use crate::vcs::testing::TempRepo;
+    use crate::{comment, export};
+
+    const RANGE_DIFF: &str = "\
+1:  3f91969 ! 1:  abbef21 Change d
+    @@ Metadata
+     Author: t <t@t>
+     
+      ## Commit message ##
+    -    v1
+    +    v2
+     
+      ## f ##
+     @@
+    @@ f
+      b
+      c
+     -d
+    -+D
+    ++D2
+      
+2:  1111111 = 2:  2222222 Same
+-:  ------- > 3:  3333333 Only new
+";
+
+    const NEW_COMMIT: &str = "\
+diff --git a/f b/f
+index 8709f80..813d963 100644
+--- a/f
++++ b/f
+@@ -1,5 +1,5 @@
+ a
+ b
+ c
+-d
++D2
+ 
+";
+
+    #[test]
+    fn pairs_become_files_with_new_line_numbers() {
+        let lines = parse(RANGE_DIFF, |sha| {
+            assert_eq!(sha, "abbef21", "only changed pairs are looked up");
+            diff::parse_diff(NEW_COMMIT)
+        })
+        .unwrap();
+
+        let headers: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.line_type == LineType::FileHeader)
+            .map(|l| l.file_path.as_str())
+            .collect();
+        assert_eq!(
+            headers,
+            ["abbef21 Change d", "2222222 Same", "3333333 Only new"]
+        );
+
+        let find = |content: &str| lines.iter().find(|l| l.content == content).unwrap();
+        // The commit message isn't part of the patch
+        let message = find("+    v2");
+        assert_eq!(
+            (message.file_path.as_str(), message.new_line_no),
+            ("abbef21 Change d", None)
+        );
+
+        let added = find("++D2");
+        assert_eq!(added.line_type, LineType::Added);
+        assert_eq!(
+            (
+                added.file_path.as_str(),
+                added.commit.as_deref(),
+                added.new_line_no
+            ),
+            ("f", Some("abbef21"), Some(4))
+        );
+        assert_eq!(find("  b").new_line_no, Some(2));
+        assert_eq!(find("  ").new_line_no, Some(5));
+
+        let removed = find("-+D");
+        assert_eq!(
+            (removed.line_type.clone(), removed.new_line_no),
+            (LineType::Removed, None)
+        );
+        assert_eq!(find("@@ f").line_type, LineType::HunkHeader);
+    }
+
+    #[test]
+    fn comments_export_with_the_file_path_and_commit() {
+        let lines = parse(RANGE_DIFF, |_| diff::parse_diff(NEW_COMMIT)).unwrap();
+        let mut app = App::new(lines, Vec::new(), ViewedState::default(), String::new());
+        app.cursor = app
+            .diff_lines
+            .iter()
+            .position(|l| l.content == "++D2")
+            .unwrap();
+        app.save_comment("Why D2?".to_string(), 1);
+
+        let exported = export::comment_values(&app.comments).unwrap();
+        assert_eq!(exported[0]["file"], "f");
+        assert_eq!(exported[0]["commit"], "abbef21");
+        assert_eq!(exported[0]["line"]["start"], 4);
+
+        // A review of the commit itself doesn't show it
+        let c = &app.comments[0];
+        assert!(comment::in_diff(c, &app.diff_lines));
+        assert!(!comment::in_diff(c, &diff::parse_diff(NEW_COMMIT).unwrap()));
+    }
+
+    #[test]
+    fn git_range_diff_output() {
+        let repo = TempRepo::new("range-diff-test");
+        let commit = |file: &str, content: &str, subject: &str| {
+            let sha = repo.commit(file, content, subject);
+            repo.run(&["rev-parse", "--short", &sha])
+        };
+        let base = commit("f", "a\nb\nc\nd\n", "Base");
+
+        // v1: two commits, then v2 rewrites the first and keeps the second
+        let v1_change = commit("f", "a\nb\nc\nD\n", "Change d");
+        let v1_same = commit("g", "g\n", "Add g");
+        repo.run(&["checkout", "-q", &base]);
+        let v2_change = commit("f", "a\nb\nc\nD2\n", "Change d");
+        let v2_same = commit("g", "g\n", "Add g");
+        let v2_new = commit("h", "h\n", "Add h");
+
+        let old = format!("{}..{}", base, v1_same);
+        let new = format!("{}..{}", base, v2_new);
+        let output = repo.run(&["range-diff", "--no-color", &old, &new]);
+        let pairs: Vec<(&str, &str, &str, String)> = output
+            .lines()
+            .filter_map(Pair::parse)
+            .map(|p| (p.old_sha, p.status, p.new_sha, p.subject))
+            .collect();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(
+            pairs[0],
+            (
+                v1_change.as_str(),
+                "!",
+                v2_change.as_str(),
+                "Change d".into()
+            )
+        );
+        assert_eq!(
+            pairs[1],
+            (v1_same.as_str(), "=", v2_same.as_str(), "Add g".into())
+        );
+        assert_eq!((pairs[2].1, pairs[2].2), (">", v2_new.as_str()));
+
+        let lines = load(VcsType::Git, repo.path(), &[&old, &new]).unwrap();
+
+        let headers: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.line_type == LineType::FileHeader)
+            .map(|l| l.file_path.as_str())
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                format!("{} Change d", v2_change),
+                format!("{} Add g", v2_same),
+                format!("{} Add h", v2_new),
+            ]
+        );
+        // The changed line is in the new commit and numbered in it
+        let added = lines.iter().find(|l| l.content == "++D2").unwrap();
+        assert_eq!(
+            (
+                added.file_path.as_str(),
+                added.commit.clone(),
+                added.new_line_no
+            ),
+            ("f", Some(v2_change.clone()), Some(4))
+        );
+        let removed = lines.iter().find(|l| l.content == "-+D").unwrap();
+        assert_eq!(removed.new_line_no, None);
+    }
+}
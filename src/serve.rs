@@ -0,0 +1,420 @@
+use crate::comment::{self, Comment, CommentStatus};
+use crate::diff;
+use crate::export;
+use crate::vcs::{self, VcsType};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// MCP revision answered when the client asks for one we don't know
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const KNOWN_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const TOOL_ERROR: i64 = -32000;
+
+/// Lines shown on each side of the line asked for by get_context, by
+/// default and at most
+const CONTEXT_RADIUS: usize = 5;
+const MAX_CONTEXT_RADIUS: usize = 1000;
+
+/// vr serve --stdio: answer JSON-RPC 2.0 requests, one per line on stdin,
+/// until stdin closes
+///
+/// Speaks the Model Context Protocol (initialize, tools/list, tools/call),
+/// and also accepts each tool name as a plain JSON-RPC method taking the
+/// tool's arguments as params. The session under .vim-review/ is re-read on
+/// every call, so comments written by a running TUI show up.
+pub fn run(vcs_ref: Option<String>, author: Option<String>) -> Result<()> {
+    let mut server = Server {
+        vcs: VcsType::detect()?,
+        root: vcs::repo_root()?,
+        vcs_ref,
+        author,
+        client: None,
+    };
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Array(batch)) => {
+                let responses: Vec<Value> = batch
+                    .into_iter()
+                    .filter_map(|request| server.handle(request))
+                    .collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Ok(request) => server.handle(request),
+            Err(e) => Some(error(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+
+        if let Some(response) = response {
+            writeln!(stdout, "{}", response)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    vcs: VcsType,
+    root: PathBuf,
+    vcs_ref: Option<String>, // Ref whose diff get_diff returns
+    author: Option<String>,  // --author, for replies that don't name one
+    client: Option<String>,  // Client name from initialize
+}
+
+impl Server {
+    /// Response to one request, or None for a notification
+    fn handle(&mut self, request: Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return Some(error(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "Missing method",
+            ));
+        };
+        let params = request.get("params").cloned().unwrap_or(json!({}));
+
+        let result = match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call_tool(&params),
+            // Notifications such as notifications/initialized need no answer
+            _ if method.starts_with("notifications/") => return None,
+            _ if tools().iter().any(|t| t["name"] == method) => self
+                .tool(method, &params)
+                .map_err(|e| (TOOL_ERROR, format!("{:#}", e))),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        };
+
+        // Requests without an id are notifications, even when they fail
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error(id, code, &message),
+        })
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        self.client = params["clientInfo"]["name"].as_str().map(str::to_string);
+        let version = params["protocolVersion"]
+            .as_str()
+            .filter(|v| KNOWN_VERSIONS.contains(v))
+            .unwrap_or(PROTOCOL_VERSION);
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "vr", "version": env!("CARGO_PKG_VERSION") },
+            "instructions": "Review comments on the current changes, left with vr. \
+                List the open comments, look at the code they point to, fix it, \
+                then reply to each thread and resolve it.",
+        })
+    }
+
+    /// MCP tools/call; a failing tool is reported in the result so the
+    /// model sees the error
+    fn call_tool(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let name = params["name"].as_str().unwrap_or_default();
+        if !tools().iter().any(|t| t["name"] == name) {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        }
+
+        let (text, is_error) = match self.tool(name, &params["arguments"]) {
+            Ok(Value::String(text)) => (text, false),
+            Ok(value) => (
+                serde_json::to_string_pretty(&value).unwrap_or_default(),
+                false,
+            ),
+            Err(e) => (format!("{:#}", e), true),
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+
+    fn tool(&self, name: &str, args: &Value) -> Result<Value> {
+        match name {
+            "list_comments" => self.list_comments(args),
+            "get_diff" => self.get_diff(args),
+            "get_context" => self.get_context(args),
+            "resolve_comment" => self.resolve_comment(args),
+            "reply" => self.reply(args),
+            _ => anyhow::bail!("Unknown tool: {}", name),
+        }
+    }
+
+    /// Threads, optionally only those with a status or in a file; threads
+    /// of pending findings are left out as they are from exports
+    fn list_comments(&self, args: &Value) -> Result<Value> {
+        let all = comment::load_comments()?;
+        let comments: Vec<Comment> = all
+            .iter()
+            .filter(|c| !thread_root(c, &all).pending)
+            .cloned()
+            .collect();
+        let status = args["status"].as_str().unwrap_or("all");
+        let file = args["file"].as_str();
+
+        let listed: Vec<Comment> = comments
+            .iter()
+            .filter(|c| {
+                let root = thread_root(c, &comments);
+                (status == "all" || root.status.label() == status)
+                    && file.is_none_or(|f| root.file_path == f)
+            })
+            .cloned()
+            .collect();
+        export::comment_values(&listed)
+    }
+
+    /// The diff under review, or one file's part of it
+    fn get_diff(&self, args: &Value) -> Result<Value> {
+        let text = self.vcs.get_diff(self.vcs_ref.as_deref())?;
+        let Some(file) = args["file"].as_str() else {
+            return Ok(Value::String(text));
+        };
+
+        let lines: Vec<String> = diff::parse_diff(&text)?
+            .into_iter()
+            .filter(|l| l.file_path == file)
+            .map(|l| l.content)
+            .collect();
+        if lines.is_empty() {
+            anyhow::bail!("{} is not in the diff", file);
+        }
+        Ok(Value::String(lines.join("\n")))
+    }
+
+    /// Numbered lines of the working tree file around a comment, or around
+    /// `file` and `line`
+    fn get_context(&self, args: &Value) -> Result<Value> {
+        let (file, line) = match args["id"].as_str() {
+            Some(id) => {
+                let comments = comment::load_comments()?;
+                let c = &comments[find(&comments, id)?];
+                (c.file_path.clone(), c.line_number)
+            }
+            None => (
+                args["file"]
+                    .as_str()
+                    .context("Pass either id, or file and line")?
+                    .to_string(),
+                args["line"]
+                    .as_u64()
+                    .context("Pass either id, or file and line")? as usize,
+            ),
+        };
+        let radius = args["lines"].as_u64().map_or(CONTEXT_RADIUS, |n| {
+            n.min(MAX_CONTEXT_RADIUS as u64) as usize
+        });
+
+        // Only files in the repository are served; ".." and absolute paths
+        // could otherwise read anything the user can
+        let path = fs::canonicalize(self.root.join(&file))
+            .with_context(|| format!("Failed to read {}", file))?;
+        if !path.starts_with(fs::canonicalize(&self.root)?) {
+            anyhow::bail!("{} is outside the repository", file);
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {}", file))?;
+        let lines: Vec<&str> = content.lines().collect();
+        if line == 0 || line > lines.len().max(1) {
+            anyhow::bail!("{} has {} lines", file, lines.len());
+        }
+
+        let first = line.saturating_sub(radius).max(1);
+        let last = line.saturating_add(radius).min(lines.len());
+        let width = last.to_string().len();
+        let mut out = format!("{}:{}\n", file, line);
+        for n in first..=last {
+            let marker = if n == line { '>' } else { ' ' };
+            out.push_str(&format!(
+                "{} {:>width$} | {}\n",
+                marker,
+                n,
+                lines[n - 1],
+                width = width
+            ));
+        }
+        Ok(Value::String(out))
+    }
+
+    /// Set a thread's status, resolved unless another is given
+    fn resolve_comment(&self, args: &Value) -> Result<Value> {
+        let id = args["id"].as_str().context("Missing id")?;
+        let status = match args["status"].as_str().unwrap_or("resolved") {
+            "open" => CommentStatus::Open,
+            "resolved" => CommentStatus::Resolved,
+            "wontfix" => CommentStatus::WontFix,
+            other => anyhow::bail!("Unknown status: {}", other),
+        };
+
+        let mut comments = comment::load_comments()?;
+        let idx = find(&comments, id)?;
+        let root = thread_root(&comments[idx], &comments).id;
+        let root = comments
+            .iter_mut()
+            .find(|c| c.id == root)
+            .expect("root is in the session");
+        root.status = status;
+        root.pending = false;
+        let updated = root.clone();
+
+        comment::save_comments(&comments)?;
+        Ok(export::comment_values(&[updated])?[0].take())
+    }
+
+    /// Add a reply to the end of a thread
+    fn reply(&self, args: &Value) -> Result<Value> {
+        let id = args["id"].as_str().context("Missing id")?;
+        let text = args["text"].as_str().context("Missing text")?;
+        if text.trim().is_empty() {
+            anyhow::bail!("Empty reply");
+        }
+
+        let mut comments = comment::load_comments()?;
+        let idx = find(&comments, id)?;
+        let mut reply = thread_root(&comments[idx], &comments).reply(text.to_string());
+        reply.author = args["author"]
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| self.author.clone())
+            .or_else(|| self.client.clone());
+        comments.push(reply.clone());
+
+        comment::save_comments(&comments)?;
+        Ok(export::comment_values(&[reply])?[0].take())
+    }
+}
+
+/// Tool descriptions for tools/list
+fn tools() -> Vec<Value> {
+    let id = json!({
+        "type": "string",
+        "description": "Comment id, or a unique prefix of it",
+    });
+    vec![
+        json!({
+            "name": "list_comments",
+            "description": "List review comments with their file, line range, text, \
+                labels, status, suggested change and diff context. Replies have \
+                in_reply_to set to the id of the thread they belong to.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "status": {
+                        "type": "string",
+                        "enum": ["open", "resolved", "wontfix", "all"],
+                        "description": "Only threads with this status (default all)",
+                    },
+                    "file": { "type": "string", "description": "Only threads on this file" },
+                },
+            },
+        }),
+        json!({
+            "name": "get_diff",
+            "description": "The unified diff under review",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "file": { "type": "string", "description": "Only this file's part of the diff" },
+                },
+            },
+        }),
+        json!({
+            "name": "get_context",
+            "description": "Numbered lines of the current file around a comment, \
+                or around a file and line",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": id,
+                    "file": { "type": "string" },
+                    "line": { "type": "integer", "minimum": 1 },
+                    "lines": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": MAX_CONTEXT_RADIUS,
+                        "description": "Lines on each side (default 5)",
+                    },
+                },
+            },
+        }),
+        json!({
+            "name": "resolve_comment",
+            "description": "Mark a comment's thread resolved, won't fix, or open again",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": id,
+                    "status": {
+                        "type": "string",
+                        "enum": ["resolved", "wontfix", "open"],
+                        "description": "Default resolved",
+                    },
+                },
+                "required": ["id"],
+            },
+        }),
+        json!({
+            "name": "reply",
+            "description": "Reply to a comment's thread, e.g. to say how it was addressed",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": id,
+                    "text": { "type": "string" },
+                    "author": { "type": "string", "description": "Defaults to the client's name" },
+                },
+                "required": ["id", "text"],
+            },
+        }),
+    ]
+}
+
+/// Index of the comment with this id or unique id prefix
+fn find(comments: &[Comment], id: &str) -> Result<usize> {
+    let id = id.trim().to_ascii_lowercase();
+    let matches: Vec<usize> = comments
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !id.is_empty() && c.id.to_string().starts_with(&id))
+        .map(|(idx, _)| idx)
+        .collect();
+    match matches.as_slice() {
+        [idx] => Ok(*idx),
+        [] => anyhow::bail!("No comment with id {}", id),
+        _ => anyhow::bail!("Comment id {} is ambiguous", id),
+    }
+}
+
+/// The comment a thread hangs off
+fn thread_root<'a>(c: &'a Comment, comments: &'a [Comment]) -> &'a Comment {
+    c.in_reply_to
+        .and_then(|root| comments.iter().find(|r| r.id == root))
+        .unwrap_or(c)
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,217 @@
+use crate::diff::FileSpan;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+const SESSION_DIR: &str = ".vim-review";
+const VIEWED_FILE: &str = ".vim-review/viewed.json";
+const REVISIONS_FILE: &str = ".vim-review/revisions.json";
+
+/// Keeps the session out of the repository's untracked files; templates
+/// are meant to be shared, so they can still be committed
+const GITIGNORE: &str = "# vr review session\n*\n!templates/\n!templates/**\n";
+
+/// Create a session directory, with a .gitignore for the session files
+pub fn create_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(gitignore, GITIGNORE)?;
+    }
+    Ok(())
+}
+
+/// Commits the session's last review was of, so the next one can look at
+/// only what changed since (vr --interdiff)
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReviewedRevisions {
+    pub base_sha: Option<String>,
+    pub head_sha: Option<String>,
+}
+
+impl ReviewedRevisions {
+    pub fn load() -> Result<Self> {
+        if !Path::new(REVISIONS_FILE).exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(REVISIONS_FILE)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        create_dir(Path::new(SESSION_DIR))?;
+
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(REVISIONS_FILE, json)?;
+
+        Ok(())
+    }
+}
+
+/// Files and hunks the reviewer has marked as viewed
+///
+/// Entries are keyed by content hash, so a file or hunk whose diff changed
+/// since it was marked shows up as unviewed again.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ViewedState {
+    files: BTreeMap<String, u64>,
+    hunks: BTreeMap<String, BTreeSet<u64>>,
+}
+
+impl ViewedState {
+    /// Load viewed state from disk
+    pub fn load() -> Result<Self> {
+        if !Path::new(VIEWED_FILE).exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(VIEWED_FILE)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Save viewed state to disk
+    pub fn save(&self) -> Result<()> {
+        create_dir(Path::new(SESSION_DIR))?;
+
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(VIEWED_FILE, json)?;
+
+        Ok(())
+    }
+
+    /// Drop entries for files in the current diff whose content changed
+    pub fn retain_current(&mut self, files: &[FileSpan]) {
+        for file in files {
+            if self.files.get(&file.path).is_some_and(|h| *h != file.hash) {
+                self.files.remove(&file.path);
+            }
+            if let Some(hunks) = self.hunks.get_mut(&file.path) {
+                hunks.retain(|h| file.hunks.iter().any(|span| span.hash == *h));
+                if hunks.is_empty() {
+                    self.hunks.remove(&file.path);
+                }
+            }
+        }
+    }
+
+    pub fn is_file_viewed(&self, file: &FileSpan) -> bool {
+        self.files.get(&file.path) == Some(&file.hash)
+    }
+
+    pub fn is_hunk_viewed(&self, file: &FileSpan, hunk_hash: u64) -> bool {
+        self.is_file_viewed(file)
+            || self
+                .hunks
+                .get(&file.path)
+                .is_some_and(|hunks| hunks.contains(&hunk_hash))
+    }
+
+    pub fn toggle_file(&mut self, file: &FileSpan) {
+        if self.is_file_viewed(file) {
+            self.files.remove(&file.path);
+        } else {
+            self.files.insert(file.path.clone(), file.hash);
+        }
+    }
+
+    pub fn toggle_hunk(&mut self, file: &FileSpan, hunk_hash: u64) {
+        // Unmarking a hunk of a viewed file keeps the rest of it viewed
+        if self
+            .files
+            .remove(&file.path)
+            .is_some_and(|h| h == file.hash)
+        {
+            let others = file
+                .hunks
+                .iter()
+                .map(|h| h.hash)
+                .filter(|h| *h != hunk_hash);
+            self.hunks.insert(file.path.clone(), others.collect());
+            if self.hunks[&file.path].is_empty() {
+                self.hunks.remove(&file.path);
+            }
+            return;
+        }
+
+        let hunks = self.hunks.entry(file.path.clone()).or_default();
+        if !hunks.remove(&hunk_hash) {
+            hunks.insert(hunk_hash);
+        }
+        if hunks.is_empty() {
+            self.hunks.remove(&file.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff;
+
+    fn files(diff_text: &str) -> Vec<FileSpan> {
+        diff::index_files(&diff::parse_diff(diff_text).unwrap())
+    }
+
+    #[test]
+    fn toggles_mark_and_unmark() {
+        let files = files(include_str!("../tests/fixtures/review.diff"));
+        let (lib, main) = (&files[0], &files[1]);
+        let mut viewed = ViewedState::default();
+
+        viewed.toggle_hunk(lib, lib.hunks[0].hash);
+        assert!(viewed.is_hunk_viewed(lib, lib.hunks[0].hash));
+        assert!(!viewed.is_hunk_viewed(lib, lib.hunks[1].hash));
+        assert!(!viewed.is_file_viewed(lib));
+
+        // A viewed file has all of its hunks viewed
+        viewed.toggle_file(main);
+        assert!(viewed.is_hunk_viewed(main, main.hunks[0].hash));
+
+        viewed.toggle_hunk(lib, lib.hunks[0].hash);
+        viewed.toggle_file(main);
+        assert!(viewed.files.is_empty() && viewed.hunks.is_empty());
+    }
+
+    #[test]
+    fn unviewing_a_hunk_of_a_viewed_file() {
+        let files = files(include_str!("../tests/fixtures/review.diff"));
+        let lib = &files[0];
+        let mut viewed = ViewedState::default();
+
+        viewed.toggle_file(lib);
+        viewed.toggle_hunk(lib, lib.hunks[0].hash);
+        assert!(!viewed.is_file_viewed(lib));
+        assert!(!viewed.is_hunk_viewed(lib, lib.hunks[0].hash));
+        assert!(viewed.is_hunk_viewed(lib, lib.hunks[1].hash));
+
+        viewed.toggle_hunk(lib, lib.hunks[0].hash);
+        assert!(lib.hunks.iter().all(|h| viewed.is_hunk_viewed(lib, h.hash)));
+    }
+
+    #[test]
+    fn changed_diffs_are_unviewed_again() {
+        let fixture = include_str!("../tests/fixtures/review.diff");
+        let old = files(fixture);
+        let mut viewed = ViewedState::default();
+        viewed.toggle_hunk(&old[0], old[0].hunks[0].hash);
+        viewed.toggle_hunk(&old[0], old[0].hunks[1].hash);
+        viewed.toggle_file(&old[0]);
+        viewed.toggle_file(&old[1]);
+
+        // The second hunk of src/lib.rs changes, src/main.rs doesn't
+        let new = files(&fixture.replace("{ 2 }", "{ 3 }"));
+        viewed.retain_current(&new);
+        let (lib, main) = (&new[0], &new[1]);
+        assert!(!viewed.is_file_viewed(lib));
+        assert!(viewed.is_hunk_viewed(lib, lib.hunks[0].hash));
+        assert!(!viewed.is_hunk_viewed(lib, lib.hunks[1].hash));
+        assert!(viewed.is_file_viewed(main));
+
+        // Files outside the current diff keep their state
+        viewed.retain_current(&new[..1]);
+        assert!(viewed.is_file_viewed(main));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::comment::{self, Comment, CommentStatus};
+use crate::diff;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Outcome of applying suggested changes to the working tree
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub applied: usize,
+    pub conflicts: Vec<String>, // "path:line: reason" for each skipped suggestion
+}
+
+impl ApplyReport {
+    /// One-line summary for the status bar or terminal
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Applied {} suggestion{}",
+            self.applied,
+            if self.applied == 1 { "" } else { "s" }
+        );
+        if !self.conflicts.is_empty() {
+            summary.push_str(&format!(", {} conflicted", self.conflicts.len()));
+        }
+        summary
+    }
+}
+
+/// Apply the suggestions of open threads to the files under `root`
+///
+/// Before patching, the comment's stored context and the replaced lines are
+/// compared with the file; if they no longer match the suggestion is skipped
+/// as a conflict. Suggestions on the commit message are left alone. Applied
+/// suggestions are marked resolved. Suggestions in one file are applied
+/// bottom-up so earlier line numbers stay valid, and a suggestion overlapping
+/// one already applied conflicts. A file that can't be written leaves its
+/// suggestions open as conflicts, so the other files' changes can still be
+/// saved to the session.
+pub fn apply_suggestions(comments: &mut [Comment], root: &Path) -> ApplyReport {
+    apply_with(comments, root, |path, content| fs::write(path, content))
+}
+
+/// apply_suggestions, writing each patched file with `write`
+fn apply_with(
+    comments: &mut [Comment],
+    root: &Path,
+    mut write: impl FnMut(&Path, String) -> io::Result<()>,
+) -> ApplyReport {
+    let mut report = ApplyReport::default();
+
+    let mut by_file: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (idx, c) in comments.iter().enumerate() {
+        // A commit message isn't a file in the working tree
+        if c.suggestion.is_some()
+            && c.file_path != diff::COMMIT_MSG
+            && c.status == CommentStatus::Open
+            && comment::is_thread_root(c, comments)
+        {
+            by_file.entry(c.file_path.clone()).or_default().push(idx);
+        }
+    }
+
+    for (file_path, mut indices) in by_file {
+        let path = root.join(&file_path);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                for idx in indices {
+                    let line = comments[idx].line_number;
+                    report
+                        .conflicts
+                        .push(format!("{}:{}: {}", file_path, line, e));
+                }
+                continue;
+            }
+        };
+
+        let newline = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        let mut applied_from = usize::MAX; // First line touched so far
+        let mut applied = Vec::new();
+
+        indices.sort_by_key(|&idx| std::cmp::Reverse(comments[idx].line_number));
+        for idx in indices {
+            let comment = &comments[idx];
+            let suggestion = comment.suggestion.as_ref().expect("filtered above");
+            let location = format!("{}:{}", file_path, comment.line_number);
+
+            if suggestion.end_line >= applied_from {
+                report
+                    .conflicts
+                    .push(format!("{}: overlaps another suggestion", location));
+                continue;
+            }
+            if let Err(reason) = check(comment, &lines) {
+                report.conflicts.push(format!("{}: {}", location, reason));
+                continue;
+            }
+
+            let start = comment.line_number - 1;
+            let end = start + suggestion.original.len();
+            let replacement = suggestion.replacement.lines().map(str::to_string);
+            lines.splice(start..end, replacement);
+
+            applied_from = comment.line_number;
+            applied.push(idx);
+        }
+
+        if applied.is_empty() {
+            continue;
+        }
+        let mut patched = lines.join(newline);
+        if content.ends_with('\n') && !lines.is_empty() {
+            patched.push_str(newline);
+        }
+        match write(&path, patched) {
+            Ok(()) => {
+                for &idx in &applied {
+                    comments[idx].status = CommentStatus::Resolved;
+                }
+                report.applied += applied.len();
+            }
+            Err(e) => {
+                for &idx in applied.iter().rev() {
+                    let line = comments[idx].line_number;
+                    report
+                        .conflicts
+                        .push(format!("{}:{}: failed to write: {}", file_path, line, e));
+                }
+            }
+        }
+    }
+
+    report
+}
+
+/// Check that the file still reads the way it did when the suggestion was
+/// written: the new-side context lines end at the comment's line, and the
+/// lines to replace are unchanged
+fn check(comment: &Comment, lines: &[String]) -> Result<(), String> {
+    let Some(suggestion) = &comment.suggestion else {
+        return Ok(());
+    };
+    let start = comment.line_number;
+    if start == 0 || start - 1 + suggestion.original.len() > lines.len() {
+        return Err("file is shorter than the suggested range".to_string());
+    }
+
+    let original = &lines[start - 1..start - 1 + suggestion.original.len()];
+    if original != suggestion.original.as_slice() {
+        return Err("lines changed since the suggestion was made".to_string());
+    }
+
+    // Removed lines aren't in the working tree, so only added and context
+    // lines take part. Each is checked at its own line number; comments
+    // from before context lines had numbers end at the comment's line.
+    let context: Vec<(Option<usize>, &str)> = comment
+        .context
+        .iter()
+        .filter(|ctx| !ctx.content.starts_with('-'))
+        .map(|ctx| (ctx.new_line_no, ctx.content.get(1..).unwrap_or("")))
+        .collect();
+    if context.len() > start {
+        return Err("context no longer matches".to_string());
+    }
+    let first = start - context.len() + 1;
+    let matches = context.iter().enumerate().all(|(i, (line_no, text))| {
+        let line_no = line_no.unwrap_or(first + i);
+        lines.get(line_no.wrapping_sub(1)).map(String::as_str) == Some(*text)
+    });
+    if !matches {
+        return Err("context no longer matches".to_string());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment::Suggestion;
+
+    fn suggest(path: &str, line: &str) -> Comment {
+        let mut c = Comment::new(path.to_string(), 1, "fix".to_string(), Vec::new());
+        c.suggestion = Some(Suggestion {
+            end_line: 1,
+            original: vec![line.to_string()],
+            replacement: "b".to_string(),
+        });
+        c
+    }
+
+    #[test]
+    fn unwritable_files_leave_their_suggestions_open() {
+        let root = std::env::temp_dir().join(format!("vr-apply-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("locked.txt"), "a\n").unwrap();
+        fs::write(root.join("open.txt"), "a\n").unwrap();
+        let mut comments = vec![suggest("locked.txt", "a"), suggest("open.txt", "a")];
+
+        let report = apply_with(&mut comments, &root, |path, content| {
+            if path.ends_with("locked.txt") {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only"));
+            }
+            fs::write(path, content)
+        });
+        let locked = fs::read_to_string(root.join("locked.txt")).unwrap();
+        let patched = fs::read_to_string(root.join("open.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.summary(), "Applied 1 suggestion, 1 conflicted");
+        assert_eq!(
+            report.conflicts,
+            ["locked.txt:1: failed to write: read-only"]
+        );
+        assert_eq!(comments[0].status, CommentStatus::Open);
+        assert_eq!(comments[1].status, CommentStatus::Resolved);
+        assert_eq!((locked.as_str(), patched.as_str()), ("a\n", "b\n"));
+    }
+
+    #[test]
+    fn changed_and_overlapping_lines_conflict() {
+        let root = std::env::temp_dir().join(format!("vr-apply-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("f.txt"), "a\nx\n").unwrap();
+        let mut overlapping = suggest("f.txt", "a");
+        overlapping.suggestion.as_mut().unwrap().end_line = 2;
+        overlapping
+            .suggestion
+            .as_mut()
+            .unwrap()
+            .original
+            .push("x".to_string());
+        let mut changed = suggest("f.txt", "y");
+        changed.line_number = 2;
+        changed.suggestion.as_mut().unwrap().end_line = 2;
+        let mut comments = vec![suggest("f.txt", "a"), overlapping, changed];
+
+        let report = apply_suggestions(&mut comments, &root);
+        let patched = fs::read_to_string(root.join("f.txt")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.applied, 1);
+        assert_eq!(
+            report.conflicts,
+            [
+                "f.txt:2: lines changed since the suggestion was made",
+                "f.txt:1: overlaps another suggestion",
+            ]
+        );
+        assert_eq!(patched, "b\nx\n");
+    }
+}
@@ -1,9 +1,12 @@
-use crate::app::{App, Mode};
+use crate::app::{App, InsertTarget, Mode};
+use crate::comment::{self, Comment, CommentStatus, Severity};
 use crate::diff::LineType;
+use crate::editor::{CommentEditor, EditorMode};
+use crate::external_editor;
 use crate::vim;
 use anyhow::Result;
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -16,12 +19,13 @@ use ratatui::{
     Terminal,
 };
 use std::io;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub fn run(app: &mut App) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
@@ -33,44 +37,48 @@ pub fn run(app: &mut App) -> Result<()> {
             // Create layout with main area and status bar
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(1),
-                    Constraint::Length(1),
-                ])
+                .constraints([Constraint::Min(1), Constraint::Length(1)])
                 .split(size);
 
             // Show help screen if in help mode
             if app.mode == Mode::Help {
-                render_help(f, chunks[0]);
+                render_help(f, chunks[0], &app.output_file);
                 let status = Paragraph::new(" Press '?' or ESC to close help")
                     .style(Style::default().bg(Color::Blue).fg(Color::White));
                 f.render_widget(status, chunks[1]);
                 return;
             }
 
+            if app.mode == Mode::HookOutput {
+                render_hook_output(f, chunks[0], app);
+                let status = Paragraph::new(" Press ESC or 'q' to close")
+                    .style(Style::default().bg(Color::Blue).fg(Color::White));
+                f.render_widget(status, chunks[1]);
+                return;
+            }
+
+            // Lines collapsed by viewed files/hunks are skipped entirely, so
+            // the scroll offset counts shown lines rather than diff lines
+            let cursor_row = app.visible_row(app.cursor).unwrap_or(0);
+
             // Adjust scroll offset to keep cursor in view
             let visible_height = chunks[0].height as usize;
-            if app.cursor >= app.scroll_offset + visible_height {
-                app.scroll_offset = app.cursor - visible_height + 1;
-            } else if app.cursor < app.scroll_offset {
-                app.scroll_offset = app.cursor;
+            if cursor_row >= app.scroll_offset + visible_height {
+                app.scroll_offset = cursor_row - visible_height + 1;
+            } else if cursor_row < app.scroll_offset {
+                app.scroll_offset = cursor_row;
             }
 
             // Render diff lines
+            let shown = app.visible_lines();
             let mut lines = Vec::new();
-            let visible_end = (app.scroll_offset + visible_height).min(app.diff_lines.len());
+            let visible_end = (app.scroll_offset + visible_height).min(shown.len());
 
-            for i in app.scroll_offset..visible_end {
+            for &i in &shown[app.scroll_offset.min(visible_end)..visible_end] {
                 let diff_line = &app.diff_lines[i];
 
                 // Check if there's a comment for this line
-                let has_comment = if let Some(line_no) = diff_line.new_line_no {
-                    app.comments.iter().any(|c| {
-                        c.file_path == diff_line.file_path && c.line_number == line_no
-                    })
-                } else {
-                    false
-                };
+                let has_comment = app.has_comment_at(i);
 
                 // Style based on line type
                 let style = match diff_line.line_type {
@@ -94,73 +102,52 @@ pub fn run(app: &mut App) -> Result<()> {
                     style
                 };
 
+                let text = match app.closed_fold_at(i) {
+                    Some(_) => fold_summary(app, i),
+                    None => diff_line.content.clone(),
+                };
                 lines.push(Line::from(Span::styled(
-                    diff_line.content.clone(),
+                    format!("{}{}", text, viewed_marker(app, i)),
                     style,
                 )));
 
-                // Show comment being typed if in insert mode at this line
-                if i == app.cursor {
-                    if let Mode::Insert { comment_text } = &app.mode {
-                        // Split multi-line comments and display each line
-                        for (idx, line) in comment_text.lines().enumerate() {
-                            let display_line = if idx == comment_text.lines().count() - 1
-                                && !comment_text.ends_with('\n') {
-                                format!("  💬 {}_", line)
-                            } else {
-                                format!("  💬 {}", line)
-                            };
-                            lines.push(Line::from(Span::styled(
-                                display_line,
-                                Style::default()
-                                    .fg(Color::Black)
-                                    .bg(Color::Yellow),
-                            )));
-                        }
-                        // If text ends with newline, show cursor on new line
-                        if comment_text.ends_with('\n') {
-                            lines.push(Line::from(Span::styled(
-                                "  💬 _",
-                                Style::default()
-                                    .fg(Color::Black)
-                                    .bg(Color::Yellow),
-                            )));
-                        }
-                        // Show empty line with cursor if no text yet
-                        if comment_text.is_empty() {
-                            lines.push(Line::from(Span::styled(
-                                "  💬 _",
-                                Style::default()
-                                    .fg(Color::Black)
-                                    .bg(Color::Yellow),
-                            )));
-                        }
+                // Comment being typed at this line: new comments go above the
+                // line's threads, replies below the thread they answer
+                let (editor, target) = match &app.mode {
+                    Mode::Insert { editor, target } if i == app.cursor => {
+                        (Some(editor), Some(target))
                     }
+                    _ => (None, None),
+                };
+                if !matches!(target, Some(InsertTarget::Reply(_))) {
+                    lines.extend(editor.map(render_editor).unwrap_or_default());
                 }
 
-                // Show comment text if present
+                // Show comment threads if present
                 if has_comment {
-                    if let Some(line_no) = diff_line.new_line_no {
-                        for comment in app.comments.iter() {
-                            if comment.file_path == diff_line.file_path
-                                && comment.line_number == line_no {
-                                // Split multi-line comments and display each line
-                                for line in comment.text.lines() {
-                                    lines.push(Line::from(Span::styled(
-                                        format!("  💬 {}", line),
-                                        Style::default()
-                                            .fg(Color::Black)
-                                            .bg(Color::Yellow),
-                                    )));
-                                }
+                    for (idx, root) in app.comments.iter().enumerate() {
+                        if !root.is_on(diff_line) || !comment::is_thread_root(root, &app.comments) {
+                            continue;
+                        }
+
+                        // The comment being edited is shown by the insert box instead
+                        if target != Some(&InsertTarget::Edit(idx)) {
+                            lines.extend(render_comment(root, root, false));
+                        }
+                        for reply in comment::replies_to(root, &app.comments) {
+                            let editing = app.comments.iter().position(|c| c.id == reply.id);
+                            if target != editing.map(InsertTarget::Edit).as_ref() {
+                                lines.extend(render_comment(reply, root, true));
                             }
                         }
+                        if target == Some(&InsertTarget::Reply(root.id)) {
+                            lines.extend(editor.map(render_editor).unwrap_or_default());
+                        }
                     }
                 }
             }
 
-            let paragraph = Paragraph::new(lines)
-                .block(Block::default().borders(Borders::NONE));
+            let paragraph = Paragraph::new(lines).block(Block::default().borders(Borders::NONE));
             f.render_widget(paragraph, chunks[0]);
 
             // Render status bar
@@ -171,19 +158,44 @@ pub fn run(app: &mut App) -> Result<()> {
                     } else {
                         ""
                     };
+                    let hint = app.message.clone().unwrap_or_else(|| {
+                        "Press ':wq' to save & quit, 'o' to comment, '?' for help".to_string()
+                    });
+                    // With --watch, when the diff was last reloaded
+                    let reloaded = app
+                        .reloaded_at
+                        .map(|t| format!("reloaded {} | ", t.format("%H:%M:%S")))
+                        .unwrap_or_default();
+                    // Show a partially typed command like vim's showcmd
                     format!(
-                        " {} | Line {}/{} | Press ':wq' to save & quit, 'o' to comment, '?' for help",
+                        " {} | Line {}/{} | {}/{} files reviewed | {}{} {}",
                         current_file,
                         app.cursor + 1,
-                        app.diff_lines.len()
+                        app.diff_lines.len(),
+                        app.reviewed_count(),
+                        app.files.len(),
+                        reloaded,
+                        hint,
+                        app.keys.pending_keys()
                     )
                 }
-                Mode::Insert { comment_text } => {
-                    let lines_count = comment_text.lines().count();
+                Mode::Insert { editor, target } => {
+                    let label = match (editor.mode, target) {
+                        (EditorMode::Normal, _) => "NORMAL",
+                        (EditorMode::Insert, InsertTarget::New) => "INSERT",
+                        (EditorMode::Insert, InsertTarget::Suggest(_)) => "SUGGEST",
+                        (EditorMode::Insert, InsertTarget::Edit(_)) => "EDIT",
+                        (EditorMode::Insert, InsertTarget::Reply(_)) => "REPLY",
+                    };
+                    let hint = match editor.mode {
+                        EditorMode::Normal => "Press ESC to save, 'i' to keep typing",
+                        EditorMode::Insert => "Press ESC for normal mode, Enter for new line",
+                    };
+                    let lines_count = editor.text().lines().count();
                     if lines_count <= 1 {
-                        format!(" -- INSERT -- Press ESC to save, Enter for new line")
+                        format!(" -- {} -- {}", label, hint)
                     } else {
-                        format!(" -- INSERT -- {} lines | Press ESC to save, Enter for new line", lines_count)
+                        format!(" -- {} -- {} lines | {}", label, lines_count, hint)
                     }
                 }
                 Mode::Search { query } => {
@@ -192,9 +204,8 @@ pub fn run(app: &mut App) -> Result<()> {
                 Mode::Command { command } => {
                     format!(" :{}", command)
                 }
-                Mode::Help => {
-                    " Press '?' or ESC to close help".to_string()
-                }
+                Mode::Help => " Press '?' or ESC to close help".to_string(),
+                Mode::HookOutput => " Press ESC or 'q' to close".to_string(),
             };
 
             let status = Paragraph::new(status_text)
@@ -204,7 +215,13 @@ pub fn run(app: &mut App) -> Result<()> {
 
         // Handle input
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            let event = event::read()?;
+            if let Event::Paste(text) = &event {
+                vim::handle_paste(app, text);
+            }
+            if let Event::Key(key) = event {
+                app.message = None;
+
                 // Handle quit in any mode
                 if key.code == KeyCode::Char('c')
                     && key
@@ -217,6 +234,15 @@ pub fn run(app: &mut App) -> Result<()> {
                 let terminal_height = terminal.size()?.height as usize;
                 vim::handle_key_event(app, key, terminal_height);
             }
+        } else {
+            app.keys.expire();
+        }
+        app.poll_hook();
+        app.poll_watch();
+
+        if app.compose_requested {
+            app.compose_requested = false;
+            compose_in_editor(&mut terminal, app)?;
         }
 
         if app.should_quit {
@@ -226,13 +252,255 @@ pub fn run(app: &mut App) -> Result<()> {
 
     // Restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableBracketedPaste
+    )?;
+    terminal.show_cursor()?;
+
+    Ok(())
+}
+
+/// Hand the terminal to $EDITOR to write the cursor line's comment, then
+/// take it back
+fn compose_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+) -> Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableBracketedPaste
+    )?;
     terminal.show_cursor()?;
 
+    let result = external_editor::compose(&app.compose_header(), &app.compose_body());
+
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableBracketedPaste
+    )?;
+    terminal.clear()?;
+
+    match result {
+        Ok(text) => app.finish_compose(text),
+        Err(e) => app.message = Some(format!("{:#}", e)),
+    }
+
     Ok(())
 }
 
-fn render_help(f: &mut ratatui::Frame, area: ratatui::layout::Rect) {
+/// Lines of a comment, coloured by the status of its thread
+fn render_comment(comment: &Comment, root: &Comment, is_reply: bool) -> Vec<Line<'static>> {
+    let style = match root.status {
+        // Imported findings stand apart until they are triaged
+        _ if root.pending => Style::default().fg(Color::Black).bg(Color::LightBlue),
+        CommentStatus::Open => Style::default().fg(Color::Black).bg(Color::Yellow),
+        CommentStatus::Resolved => Style::default().fg(Color::Black).bg(Color::Green),
+        CommentStatus::WontFix => Style::default().fg(Color::White).bg(Color::DarkGray),
+    };
+
+    let mut badge = if is_reply {
+        String::new()
+    } else if root.pending {
+        "[pending] ".to_string()
+    } else if root.status != CommentStatus::Open {
+        format!("[{}] ", root.status.label())
+    } else {
+        String::new()
+    };
+    if let Some(name) = comment.author_name() {
+        badge.push_str(&format!("{}: ", name));
+    }
+    let edited = if comment.edited_at.is_some() {
+        " (edited)"
+    } else {
+        ""
+    };
+
+    // Severity/category tags get their own colour so blockers stand out
+    let labels = if is_reply || comment.labels().is_empty() {
+        None
+    } else {
+        let text = comment
+            .labels()
+            .iter()
+            .map(|l| format!("[{}]", l))
+            .collect::<String>();
+        Some(Span::styled(
+            format!("{} ", text),
+            severity_style(comment.severity),
+        ))
+    };
+
+    // A comment that is only a suggestion still gets a header line
+    let mut text: Vec<&str> = comment.text.lines().collect();
+    if text.is_empty() {
+        text.push("");
+    }
+    let count = text.len();
+    let mut lines: Vec<Line<'static>> = text
+        .into_iter()
+        .enumerate()
+        .map(|(n, line)| {
+            let marker = match (is_reply, n) {
+                (false, 0) => "  💬 ",
+                (false, _) => "     ",
+                (true, 0) => "     ↳ ",
+                (true, _) => "       ",
+            };
+            let badge = if n == 0 { badge.as_str() } else { "" };
+            let suffix = if n + 1 == count { edited } else { "" };
+
+            let mut spans = vec![Span::styled(marker, style)];
+            if n == 0 {
+                spans.extend(labels.clone());
+            }
+            spans.push(Span::styled(format!("{}{}{}", badge, line, suffix), style));
+            Line::from(spans)
+        })
+        .collect();
+
+    // Suggested change as a small diff of the lines it replaces
+    if let Some(suggestion) = &comment.suggestion {
+        let removed = suggestion
+            .original
+            .iter()
+            .map(|l| ('-', l.as_str(), Color::Red));
+        let added = suggestion
+            .replacement
+            .lines()
+            .map(|l| ('+', l, Color::Green));
+        for (sign, line, color) in removed.chain(added) {
+            lines.push(Line::from(vec![
+                Span::styled("     ", style),
+                Span::styled(format!("{}{}", sign, line), Style::default().fg(color)),
+            ]));
+        }
+    }
+    lines
+}
+
+/// Output of the last hook, with its command and exit status as the title
+fn render_hook_output(f: &mut ratatui::Frame, area: ratatui::layout::Rect, app: &App) {
+    let Some(result) = &app.hook_result else {
+        return;
+    };
+    let status = match &result.status {
+        Ok(code) => format!("exited with {}", code),
+        Err(e) => format!("failed: {}", e),
+    };
+    let title = format!(" {} `{}` {} ", result.event.name(), result.command, status);
+    let color = if result.success() {
+        Color::Green
+    } else {
+        Color::Red
+    };
+
+    let lines: Vec<Line> = result
+        .output
+        .lines()
+        .map(|l| Line::from(l.to_string()))
+        .collect();
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color))
+            .title(title),
+    );
+    f.render_widget(paragraph, area);
+}
+
+fn severity_style(severity: Option<Severity>) -> Style {
+    let style = Style::default().add_modifier(Modifier::BOLD);
+    match severity {
+        Some(Severity::Blocker) => style.fg(Color::White).bg(Color::Red),
+        Some(Severity::Issue) => style.fg(Color::Black).bg(Color::LightRed),
+        Some(Severity::Suggestion) => style.fg(Color::Black).bg(Color::Cyan),
+        Some(Severity::Nit) => style.fg(Color::Black).bg(Color::Gray),
+        None => style.fg(Color::Black).bg(Color::Magenta),
+    }
+}
+
+/// Lines of the comment being typed, with the cursor shown in reverse video
+fn render_editor(editor: &CommentEditor) -> Vec<Line<'static>> {
+    let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let cursor_style = style.add_modifier(Modifier::REVERSED);
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    for text in editor.text().split('\n') {
+        let line_end = line_start + text.len();
+        let mut spans = vec![Span::styled("  💬 ", style)];
+
+        if (line_start..=line_end).contains(&editor.cursor()) {
+            let at = editor.cursor() - line_start;
+            let (before, rest) = text.split_at(at);
+            let cursor_len = rest.graphemes(true).next().map_or(0, str::len);
+            let (under, after) = rest.split_at(cursor_len);
+            spans.push(Span::styled(before.to_string(), style));
+            // Past the end of the line the cursor is a blank cell
+            let under = if under.is_empty() { " " } else { under };
+            spans.push(Span::styled(under.to_string(), cursor_style));
+            spans.push(Span::styled(after.to_string(), style));
+        } else {
+            spans.push(Span::styled(text.to_string(), style));
+        }
+
+        lines.push(Line::from(spans));
+        line_start = line_end + 1;
+    }
+    lines
+}
+
+/// One-line summary rendered in place of a closed fold
+fn fold_summary(app: &App, index: usize) -> String {
+    let Some(file) = app.file_index_at(index).map(|i| &app.files[i]) else {
+        return app.diff_lines[index].content.clone();
+    };
+
+    if index == file.start {
+        let hunks = file.hunks.len();
+        format!(
+            "▸ {} (+{} -{}, {} hunk{})",
+            file.path,
+            file.added,
+            file.removed,
+            hunks,
+            if hunks == 1 { "" } else { "s" }
+        )
+    } else if let Some(hunk) = file.hunk_at(index) {
+        format!(
+            "▸ {} (+{} -{})",
+            app.diff_lines[index].content, hunk.added, hunk.removed
+        )
+    } else {
+        app.diff_lines[index].content.clone()
+    }
+}
+
+/// Suffix shown on the header line of a viewed file or hunk
+fn viewed_marker(app: &App, index: usize) -> &'static str {
+    let Some(file) = app.file_index_at(index).map(|i| &app.files[i]) else {
+        return "";
+    };
+
+    if index == file.start && app.is_file_reviewed(file) {
+        return "  ✓ viewed";
+    }
+    match file.hunk_at(index) {
+        Some(hunk) if index == hunk.start && app.viewed.is_hunk_viewed(file, hunk.hash) => {
+            "  ✓ viewed"
+        }
+        _ => "",
+    }
+}
+
+fn render_help(f: &mut ratatui::Frame, area: ratatui::layout::Rect, output_file: &str) {
     let help_text = vec![
         Line::from(Span::styled(
             "Vim Review - Keyboard Shortcuts",
@@ -247,19 +515,54 @@ fn render_help(f: &mut ratatui::Frame, area: ratatui::layout::Rect) {
         )),
         Line::from("  j / ↓         Move cursor down"),
         Line::from("  k / ↑         Move cursor up"),
-        Line::from("  g             Jump to top"),
+        Line::from("  gg            Jump to top"),
         Line::from("  G             Jump to bottom"),
+        Line::from("  {count}G/gg   Jump to line (e.g. 10G); counts work with motions (5j)"),
         Line::from("  Ctrl+d        Page down"),
         Line::from("  Ctrl+u        Page up"),
+        Line::from("  ]c / [c       Next / previous hunk"),
+        Line::from("  ]f / [f       Next / previous file (also } / {)"),
+        Line::from("  ]m / [m       Next / previous comment"),
+        Line::from("  ]x            Next unreviewed file"),
         Line::from(""),
         Line::from(Span::styled(
             "Comments:",
             Style::default().add_modifier(Modifier::BOLD),
         )),
-        Line::from("  o / O         Add comment at current line"),
+        Line::from("  o             Add comment at current line"),
+        Line::from("  O / :edit     Write comment at current line in $EDITOR"),
         Line::from("  Enter         Add new line (in insert mode)"),
-        Line::from("  ESC           Save comment and exit insert mode"),
-        Line::from("  d             Delete comment at current line"),
+        Line::from("  ←/→/↑/↓       Move within the comment (in insert mode)"),
+        Line::from("  Ctrl+w/Ctrl+u Delete word / to start of line (in insert mode)"),
+        Line::from("  ESC           Switch to normal mode inside the comment"),
+        Line::from("  ESC ESC       Save comment and exit insert mode"),
+        Line::from("  dd            Delete comment at current line"),
+        Line::from("  cc / e        Edit comment at current line"),
+        Line::from("  r             Reply to the thread at current line"),
+        Line::from("  R             Resolve / reopen the thread at current line"),
+        Line::from("  :wontfix      Mark the thread at current line won't fix"),
+        Line::from("  s / S         Cycle severity / category of the thread"),
+        Line::from("  [blocker]     Tag prefix in comment text sets severity/category"),
+        Line::from("  yy            Yank comment (or code) at current line"),
+        Line::from("  gs / 3gs      Suggest a change to the current line(s)"),
+        Line::from("  :apply        Apply open suggestions to the working tree"),
+        Line::from("  A / X         Accept / dismiss the imported finding at current line"),
+        Line::from("  :hook         Show the output of the last on_save hook"),
+        Line::from(""),
+        Line::from(Span::styled(
+            "Review progress:",
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from("  v             Mark file as viewed (collapses it)"),
+        Line::from("  V             Mark hunk as viewed (collapses it)"),
+        Line::from(""),
+        Line::from(Span::styled(
+            "Folding:",
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from("  za            Toggle fold under cursor"),
+        Line::from("  zo / zc       Open / close fold under cursor"),
+        Line::from("  zR / zM       Open / close all folds"),
         Line::from(""),
         Line::from(Span::styled(
             "Search:",
@@ -281,7 +584,7 @@ fn render_help(f: &mut ratatui::Frame, area: ratatui::layout::Rect) {
         Line::from("  Ctrl+c        Force quit"),
         Line::from(""),
         Line::from(Span::styled(
-            "Comments are saved to /tmp/vr/{uuid}.txt",
+            format!("Comments are saved to {}", output_file),
             Style::default().fg(Color::Yellow),
         )),
     ];
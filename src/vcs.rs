@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -11,27 +12,79 @@ pub enum VcsType {
 impl VcsType {
     /// Auto-detect which VCS is being used by walking up the directory tree
     pub fn detect() -> Result<Self> {
-        let mut current_dir = env::current_dir().context("Failed to get current directory")?;
+        locate().map(|(vcs_type, _)| vcs_type)
+    }
 
-        loop {
-            // Check for .git directory
-            if current_dir.join(".git").exists() {
-                return Ok(VcsType::Git);
-            }
+    /// Short name used in exports
+    pub fn name(&self) -> &'static str {
+        match self {
+            VcsType::Git => "git",
+            VcsType::Mercurial => "hg",
+        }
+    }
 
-            // Check for .hg directory
-            if current_dir.join(".hg").exists() {
-                return Ok(VcsType::Mercurial);
+    /// Who signs new comments: the `--author` given, or else the reviewer
+    /// identity from the VCS config in `root`, e.g. "Jane Doe <jane@example.com>"
+    /// - git: user.name and user.email, or whichever of them is set
+    /// - mercurial: ui.username
+    pub fn author(&self, root: &Path, flag: Option<String>) -> Option<String> {
+        flag.or_else(|| match self {
+            VcsType::Git => {
+                let name = read_output_in(root, "git", &["config", "user.name"]);
+                let email = read_output_in(root, "git", &["config", "user.email"]);
+                match (name, email) {
+                    (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+                    (name, email) => name.or(email),
+                }
             }
+            VcsType::Mercurial => read_output_in(root, "hg", &["config", "ui.username"]),
+        })
+    }
 
-            // Move up to parent directory
-            match current_dir.parent() {
-                Some(parent) => current_dir = parent.to_path_buf(),
-                None => break,
+    /// Commit ids on either side of the reviewed diff, as (base, head)
+    ///
+    /// The head is `None` when reviewing uncommitted changes, since those
+    /// live in the working tree.
+    pub fn revisions(&self, vcs_ref: Option<&str>) -> (Option<String>, Option<String>) {
+        match self {
+            VcsType::Git => {
+                let rev_parse = |rev: &str| {
+                    let rev = if rev.is_empty() { "HEAD" } else { rev };
+                    read_output("git", &["rev-parse", "--verify", "--quiet", rev])
+                };
+                match vcs_ref {
+                    None => (rev_parse("HEAD"), None),
+                    Some(range) => match range.split_once("...") {
+                        // git diff A...B compares B with the merge base
+                        Some((a, b)) => {
+                            let a = if a.is_empty() { "HEAD" } else { a };
+                            let b = if b.is_empty() { "HEAD" } else { b };
+                            (read_output("git", &["merge-base", a, b]), rev_parse(b))
+                        }
+                        None => match range.split_once("..") {
+                            Some((a, b)) => (rev_parse(a), rev_parse(b)),
+                            None => (
+                                rev_parse(&format!("{}^", range)),
+                                rev_parse(&format!("{}^{{commit}}", range)),
+                            ),
+                        },
+                    },
+                }
+            }
+            VcsType::Mercurial => {
+                let node = |revset: String| {
+                    read_output("hg", &["log", "-r", &revset, "--template", "{node}"])
+                };
+                match vcs_ref {
+                    None => (node(".".to_string()), None),
+                    Some(range) if range.contains("::") => (
+                        node(format!("p1(min({}))", range)),
+                        node(format!("max({})", range)),
+                    ),
+                    Some(rev) => (node(format!("p1({})", rev)), node(rev.to_string())),
+                }
             }
         }
-
-        anyhow::bail!("Not a git or mercurial repository")
     }
 
     /// Get diff text from the detected VCS
@@ -45,6 +98,37 @@ impl VcsType {
     }
 }
 
+/// Top-level directory of the repository containing the current directory;
+/// diff paths are relative to it
+pub fn repo_root() -> Result<PathBuf> {
+    locate().map(|(_, root)| root)
+}
+
+/// Walk up from the current directory to the first .git or .hg
+fn locate() -> Result<(VcsType, PathBuf)> {
+    let mut current_dir = env::current_dir().context("Failed to get current directory")?;
+
+    loop {
+        // Check for .git directory
+        if current_dir.join(".git").exists() {
+            return Ok((VcsType::Git, current_dir));
+        }
+
+        // Check for .hg directory
+        if current_dir.join(".hg").exists() {
+            return Ok((VcsType::Mercurial, current_dir));
+        }
+
+        // Move up to parent directory
+        match current_dir.parent() {
+            Some(parent) => current_dir = parent.to_path_buf(),
+            None => break,
+        }
+    }
+
+    anyhow::bail!("Not a git or mercurial repository")
+}
+
 /// Get diff text from git
 /// - None: uncommitted changes (git diff HEAD)
 /// - Some("HEAD"): last commit (git show HEAD)
@@ -55,21 +139,21 @@ fn get_git_diff(git_ref: Option<&str>) -> Result<String> {
         None => {
             // Get uncommitted changes
             Command::new("git")
-                .args(&["diff", "HEAD"])
+                .args(["diff", "HEAD"])
                 .output()
                 .context("Failed to run git diff")?
         }
         Some(ref_str) if ref_str.contains("..") => {
             // Branch diff
             Command::new("git")
-                .args(&["diff", ref_str])
+                .args(["diff", ref_str])
                 .output()
                 .context("Failed to run git diff")?
         }
         Some(ref_str) => {
             // Specific commit
             Command::new("git")
-                .args(&["show", ref_str])
+                .args(["show", ref_str])
                 .output()
                 .context("Failed to run git show")?
         }
@@ -93,21 +177,21 @@ fn get_hg_diff(hg_ref: Option<&str>) -> Result<String> {
         None => {
             // Get uncommitted changes
             Command::new("hg")
-                .args(&["diff"])
+                .args(["diff"])
                 .output()
                 .context("Failed to run hg diff")?
         }
         Some(ref_str) if ref_str.contains("::") => {
             // Range of changesets (e.g., "branch1::branch2")
             Command::new("hg")
-                .args(&["log", "-p", "-r", ref_str])
+                .args(["log", "-p", "-r", ref_str])
                 .output()
                 .context("Failed to run hg log")?
         }
         Some(ref_str) => {
             // Specific changeset (export shows it as a diff)
             Command::new("hg")
-                .args(&["export", ref_str])
+                .args(["export", ref_str])
                 .output()
                 .context("Failed to run hg export")?
         }
@@ -120,3 +204,144 @@ fn get_hg_diff(hg_ref: Option<&str>) -> Result<String> {
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
+
+/// Output of a git command in `root`, as is: a patch's trailing blank
+/// context line must survive
+pub fn git(root: &Path, args: &[&str]) -> Result<String> {
+    run_git(&mut git_command(root, args))
+}
+
+/// A git command in `root`, for callers that need more set on it first
+pub fn git_command(root: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(root);
+    command
+}
+
+/// Output of a git command, or its error message if it failed
+pub fn run_git(command: &mut Command) -> Result<String> {
+    let name = command
+        .get_args()
+        .next()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run git {}", name))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Git command failed: {}", error);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Trimmed output of a command such as a config lookup, treating failure or
+/// empty output as missing
+fn read_output(program: &str, args: &[&str]) -> Option<String> {
+    read_output_in(Path::new("."), program, args)
+}
+
+/// Like `read_output`, but run in `dir`
+fn read_output_in(dir: &Path, program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    /// A throwaway git repository, removed when dropped
+    pub struct TempRepo {
+        root: PathBuf,
+    }
+
+    impl TempRepo {
+        pub fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("vr-{}-{}", name, uuid::Uuid::new_v4()));
+            fs::create_dir_all(&root).unwrap();
+            let repo = Self { root };
+            repo.run(&["init", "-q"]);
+            repo
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.root
+        }
+
+        /// Run git in the repository as a test user, returning what it printed
+        pub fn run(&self, args: &[&str]) -> String {
+            let output = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=t",
+                    "-c",
+                    "user.email=t@t",
+                    "-c",
+                    "commit.gpgsign=false",
+                ])
+                .args(args)
+                .current_dir(&self.root)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string()
+        }
+
+        /// Commit `file` with the given content, returning the commit's SHA
+        pub fn commit(&self, file: &str, content: &str, subject: &str) -> String {
+            fs::write(self.root.join(file), content).unwrap();
+            self.run(&["add", file]);
+            self.run(&["commit", "-qm", subject]);
+            self.run(&["rev-parse", "HEAD"])
+        }
+    }
+
+    impl Drop for TempRepo {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::TempRepo;
+    use super::*;
+
+    #[test]
+    fn author_from_git_config_unless_given() {
+        let repo = TempRepo::new("author-test");
+        let author = |flag: Option<&str>| VcsType::Git.author(repo.path(), flag.map(String::from));
+
+        repo.run(&["config", "user.name", "Jane Doe"]);
+        repo.run(&["config", "user.email", "jane@example.com"]);
+        assert_eq!(author(None).as_deref(), Some("Jane Doe <jane@example.com>"));
+        assert_eq!(author(Some("bot")).as_deref(), Some("bot"));
+
+        // Either half on its own still identifies the reviewer
+        repo.run(&["config", "user.email", ""]);
+        assert_eq!(author(None).as_deref(), Some("Jane Doe"));
+        repo.run(&["config", "user.name", ""]);
+        repo.run(&["config", "user.email", "jane@example.com"]);
+        assert_eq!(author(None).as_deref(), Some("jane@example.com"));
+    }
+}
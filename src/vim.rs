@@ -1,5 +1,116 @@
 use crate::app::{App, Mode};
+use crate::editor::{CommentEditor, EditorMode};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::time::{Duration, Instant};
+
+/// How long a prefix key waits for the rest of its command, like vim's
+/// 'timeoutlen'
+const KEY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Largest count a command takes; longer counts stop growing
+const MAX_COUNT: usize = 1_000_000;
+
+/// Keys that start a multi-key command instead of acting on their own
+const PREFIX_KEYS: &[char] = &['z', ']', '[', 'g', 'd', 'y', 'c'];
+
+/// A complete normal mode command: an optional count, an optional prefix
+/// key (or operator) and the final key
+#[derive(Debug, Clone, Copy)]
+struct NormalCommand {
+    count: Option<usize>,
+    prefix: Option<char>,
+    key: KeyEvent,
+}
+
+impl NormalCommand {
+    fn count_or(&self, default: usize) -> usize {
+        self.count.unwrap_or(default)
+    }
+}
+
+/// Accumulates normal mode keys until they form a complete command
+///
+/// Follows vim's grammar: `[count][prefix]key`, where the prefix is either a
+/// command prefix (`z`, `]`, `[`, `g`) or an operator (`d`, `y`, `c`) that is
+/// completed by repeating it.
+#[derive(Debug, Default)]
+pub struct KeyParser {
+    count: Option<usize>,
+    prefix: Option<char>,
+    last_key: Option<Instant>,
+}
+
+impl KeyParser {
+    /// Feed one key, returning the command once it is complete
+    fn feed(&mut self, key: KeyEvent) -> Option<NormalCommand> {
+        self.last_key = Some(Instant::now());
+
+        if key.code == KeyCode::Esc {
+            self.reset();
+            return None;
+        }
+
+        let plain_char = match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => Some(c),
+            _ => None,
+        };
+
+        if self.prefix.is_none() {
+            match plain_char {
+                // "0" only extends a count; on its own it isn't a count
+                Some(c @ '0'..='9') if c != '0' || self.count.is_some() => {
+                    let digit = c.to_digit(10).unwrap_or(0) as usize;
+                    self.count = Some(
+                        self.count
+                            .unwrap_or(0)
+                            .saturating_mul(10)
+                            .saturating_add(digit)
+                            .min(MAX_COUNT),
+                    );
+                    return None;
+                }
+                Some(c) if PREFIX_KEYS.contains(&c) => {
+                    self.prefix = Some(c);
+                    return None;
+                }
+                _ => {}
+            }
+        }
+
+        let command = NormalCommand {
+            count: self.count,
+            prefix: self.prefix,
+            key,
+        };
+        self.reset();
+        Some(command)
+    }
+
+    /// Drop a partially typed command once it has waited too long
+    pub fn expire(&mut self) {
+        if self.is_pending() && self.last_key.is_some_and(|at| at.elapsed() >= KEY_TIMEOUT) {
+            self.reset();
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || self.prefix.is_some()
+    }
+
+    /// The keys typed so far, for display like vim's 'showcmd'
+    pub fn pending_keys(&self) -> String {
+        let mut keys = self.count.map(|n| n.to_string()).unwrap_or_default();
+        if let Some(prefix) = self.prefix {
+            keys.push(prefix);
+        }
+        keys
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.prefix = None;
+    }
+}
 
 pub fn handle_key_event(app: &mut App, key: KeyEvent, terminal_height: usize) {
     match &app.mode {
@@ -7,22 +118,38 @@ pub fn handle_key_event(app: &mut App, key: KeyEvent, terminal_height: usize) {
         Mode::Insert { .. } => handle_insert_mode(app, key),
         Mode::Search { .. } => handle_search_mode(app, key),
         Mode::Command { .. } => handle_command_mode(app, key),
-        Mode::Help => handle_help_mode(app, key),
+        Mode::Help | Mode::HookOutput => handle_help_mode(app, key),
     }
 }
 
 fn handle_normal_mode(app: &mut App, key: KeyEvent, terminal_height: usize) {
+    let Some(command) = app.keys.feed(key) else {
+        return;
+    };
+
+    match command.prefix {
+        None => handle_normal_key(app, command, terminal_height),
+        Some(prefix) => handle_prefixed_key(app, prefix, command),
+    }
+}
+
+fn handle_normal_key(app: &mut App, command: NormalCommand, terminal_height: usize) {
+    let key = command.key;
+    let count = command.count_or(1);
+
     match key.code {
         // Quit
         KeyCode::Char('q') => app.quit(),
 
         // Navigation
-        KeyCode::Char('j') | KeyCode::Down => app.move_cursor_down(),
-        KeyCode::Char('k') | KeyCode::Up => app.move_cursor_up(),
+        KeyCode::Char('j') | KeyCode::Down => repeat(app, count, App::move_cursor_down),
+        KeyCode::Char('k') | KeyCode::Up => repeat(app, count, App::move_cursor_up),
 
-        // Jump to top/bottom
-        KeyCode::Char('g') => app.move_to_top(),
-        KeyCode::Char('G') => app.move_to_bottom(),
+        // Jump to bottom, or to a line with a count
+        KeyCode::Char('G') => match command.count {
+            Some(line) => app.move_to_line(line),
+            None => app.move_to_bottom(),
+        },
 
         // Page navigation
         KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -35,19 +162,30 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent, terminal_height: usize) {
         }
 
         // Insert mode (comment)
-        KeyCode::Char('o') | KeyCode::Char('O') => app.enter_insert_mode(),
+        KeyCode::Char('o') => app.enter_insert_mode(),
+        KeyCode::Char('O') => app.request_compose(),
+        KeyCode::Char('e') => app.edit_comment_at_cursor(),
 
-        // Delete comment
-        KeyCode::Char('d') => {
-            // Wait for second 'd'
-            // For simplicity, we'll handle dd in a single press
-            app.delete_comment_at_cursor();
-        }
+        // Comment threads
+        KeyCode::Char('r') => app.start_reply(),
+        KeyCode::Char('R') => app.toggle_thread_resolved(),
+        KeyCode::Char('A') => app.accept_pending(),
+        KeyCode::Char('X') => app.dismiss_pending(),
+        KeyCode::Char('s') => app.cycle_severity(),
+        KeyCode::Char('S') => app.cycle_category(),
+
+        // Review progress
+        KeyCode::Char('v') => app.toggle_file_viewed(),
+        KeyCode::Char('V') => app.toggle_hunk_viewed(),
+
+        // Structural motions
+        KeyCode::Char('}') => repeat(app, count, App::next_file),
+        KeyCode::Char('{') => repeat(app, count, App::prev_file),
 
         // Search
         KeyCode::Char('/') => app.enter_search_mode(),
-        KeyCode::Char('n') => app.next_match(),
-        KeyCode::Char('N') => app.prev_match(),
+        KeyCode::Char('n') => repeat(app, count, App::next_match),
+        KeyCode::Char('N') => repeat(app, count, App::prev_match),
 
         // Command mode
         KeyCode::Char(':') => app.enter_command_mode(),
@@ -59,17 +197,169 @@ fn handle_normal_mode(app: &mut App, key: KeyEvent, terminal_height: usize) {
     }
 }
 
+/// Second key of a two-key command such as "za", "]c", "gg" or "dd"; an
+/// unknown second key just cancels the prefix
+fn handle_prefixed_key(app: &mut App, prefix: char, command: NormalCommand) {
+    let KeyCode::Char(c) = command.key.code else {
+        return;
+    };
+    if command.key.modifiers.contains(KeyModifiers::CONTROL) {
+        return;
+    }
+    let count = command.count_or(1);
+
+    match (prefix, c) {
+        ('z', 'a') => app.toggle_fold(),
+        ('z', 'o') => app.open_fold_at_cursor(),
+        ('z', 'c') => app.close_fold_at_cursor(),
+        ('z', 'R') => app.open_all_folds(),
+        ('z', 'M') => app.close_all_folds(),
+
+        (']', 'c') => repeat(app, count, App::next_hunk),
+        (']', 'f') => repeat(app, count, App::next_file),
+        (']', 'm') => repeat(app, count, App::next_comment),
+        (']', 'x') => repeat(app, count, App::next_unreviewed_file),
+        ('[', 'c') => repeat(app, count, App::prev_hunk),
+        ('[', 'f') => repeat(app, count, App::prev_file),
+        ('[', 'm') => repeat(app, count, App::prev_comment),
+
+        // Jump to top, or to a line with a count
+        ('g', 'g') => match command.count {
+            Some(line) => app.move_to_line(line),
+            None => app.move_to_top(),
+        },
+        ('g', 's') => app.start_suggestion(count),
+
+        // Linewise operators, applied to `count` lines from the cursor
+        ('d', 'd') => app.delete_comments(count),
+        ('y', 'y') => app.yank_lines(count),
+        ('c', 'c') => app.edit_comment_at_cursor(),
+
+        _ => {}
+    }
+}
+
+/// Run a motion `count` times, stopping early once the cursor stops moving
+fn repeat(app: &mut App, count: usize, motion: fn(&mut App)) {
+    for _ in 0..count {
+        let cursor = app.cursor;
+        motion(app);
+        if app.cursor == cursor {
+            break;
+        }
+    }
+}
+
 fn handle_insert_mode(app: &mut App, key: KeyEvent) {
-    if let Mode::Insert { comment_text } = &mut app.mode {
-        match key.code {
-            KeyCode::Esc => app.exit_to_normal_mode(),
-            KeyCode::Char(c) => comment_text.push(c),
-            KeyCode::Backspace => {
-                comment_text.pop();
+    let Mode::Insert { editor, .. } = &mut app.mode else {
+        return;
+    };
+
+    match editor.mode {
+        EditorMode::Insert => handle_editor_insert(editor, key),
+        EditorMode::Normal => {
+            if key.code == KeyCode::Esc {
+                app.exit_to_normal_mode();
+            } else {
+                handle_editor_normal(editor, key, &app.register);
             }
-            KeyCode::Enter => comment_text.push('\n'),
+        }
+    }
+}
+
+/// Typing into the comment
+fn handle_editor_insert(editor: &mut CommentEditor, key: KeyEvent) {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+    match key.code {
+        KeyCode::Esc => editor.enter_normal_mode(),
+        KeyCode::Char('w') if ctrl => editor.delete_word_before(),
+        KeyCode::Char('u') if ctrl => editor.delete_to_line_start(),
+        KeyCode::Char(_) if ctrl => {}
+        KeyCode::Char(c) => editor.insert_char(c),
+        KeyCode::Backspace => editor.backspace(),
+        KeyCode::Delete => editor.delete(),
+        KeyCode::Enter => editor.insert_char('\n'),
+        KeyCode::Left => editor.move_left(),
+        KeyCode::Right => editor.move_right(),
+        KeyCode::Up => editor.move_up(),
+        KeyCode::Down => editor.move_down(),
+        KeyCode::Home => editor.move_line_start(),
+        KeyCode::End => editor.move_line_end(),
+        _ => {}
+    }
+}
+
+/// Vim-style edits on the comment, entered with ESC from typing
+fn handle_editor_normal(editor: &mut CommentEditor, key: KeyEvent, register: &str) {
+    let KeyCode::Char(c) = key.code else {
+        match key.code {
+            KeyCode::Left => editor.move_left(),
+            KeyCode::Right => editor.move_right(),
+            KeyCode::Up => editor.move_up(),
+            KeyCode::Down => editor.move_down(),
             _ => {}
         }
+        return;
+    };
+
+    if let Some(operator) = editor.pending.take() {
+        if (operator, c) == ('d', 'd') {
+            editor.delete_line();
+        }
+        return;
+    }
+
+    match c {
+        'h' => editor.move_left(),
+        'l' => editor.move_right(),
+        'k' => editor.move_up(),
+        'j' => editor.move_down(),
+        '0' | '^' => editor.move_line_start(),
+        '$' => editor.move_line_end(),
+        'w' => editor.move_word_forward(),
+        'b' => editor.move_word_backward(),
+        'x' => editor.delete(),
+        'X' => editor.backspace(),
+        'D' => editor.delete_to_line_end(),
+        'd' => editor.pending = Some('d'),
+        'p' => editor.insert_str(register),
+        'i' => editor.enter_insert_mode(),
+        'a' => {
+            editor.move_right();
+            editor.enter_insert_mode();
+        }
+        'I' => {
+            editor.move_line_start();
+            editor.enter_insert_mode();
+        }
+        'A' => {
+            editor.move_line_end();
+            editor.enter_insert_mode();
+        }
+        'o' => {
+            editor.open_line_below();
+            editor.enter_insert_mode();
+        }
+        'O' => {
+            editor.open_line_above();
+            editor.enter_insert_mode();
+        }
+        _ => {}
+    }
+}
+
+/// Text pasted into the terminal (bracketed paste) arrives in one piece
+/// rather than as key presses, so newlines don't trigger Enter
+pub fn handle_paste(app: &mut App, text: &str) {
+    match &mut app.mode {
+        Mode::Insert { editor, .. } => {
+            editor.enter_insert_mode();
+            editor.insert_str(text);
+        }
+        Mode::Search { query } => query.push_str(text.lines().next().unwrap_or("")),
+        Mode::Command { command } => command.push_str(text.lines().next().unwrap_or("")),
+        _ => {}
     }
 }
 
@@ -121,3 +411,75 @@ fn handle_command_mode(app: &mut App, key: KeyEvent) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(parser: &mut KeyParser, keys: &str) -> Option<NormalCommand> {
+        keys.chars()
+            .map(|c| parser.feed(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn counts_and_prefixes() {
+        let mut parser = KeyParser::default();
+        let command = feed(&mut parser, "12j").unwrap();
+        assert_eq!((command.count, command.prefix), (Some(12), None));
+
+        assert!(feed(&mut parser, "3d").is_none());
+        assert!(parser.is_pending());
+        assert_eq!(parser.pending_keys(), "3d");
+        let command = feed(&mut parser, "d").unwrap();
+        assert_eq!((command.count, command.prefix), (Some(3), Some('d')));
+        assert!(!parser.is_pending());
+
+        // 0 on its own is a motion, but extends a count
+        assert_eq!(feed(&mut parser, "0").unwrap().count, None);
+        assert_eq!(feed(&mut parser, "10G").unwrap().count, Some(10));
+    }
+
+    #[test]
+    fn escape_drops_a_pending_command() {
+        let mut parser = KeyParser::default();
+        feed(&mut parser, "5z");
+        assert!(parser
+            .feed(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .is_none());
+        assert!(!parser.is_pending());
+
+        // Ctrl-d isn't a prefix even though d is
+        feed(&mut parser, "2");
+        let command = parser
+            .feed(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!((command.count, command.prefix), (Some(2), None));
+    }
+
+    #[test]
+    fn counts_repeat_motions_until_they_stop() {
+        let diff = crate::diff::parse_diff(include_str!("../tests/fixtures/review.diff")).unwrap();
+        let mut app = App::new(diff, Vec::new(), Default::default(), String::new());
+        let press = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                handle_key_event(app, KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), 20);
+            }
+        };
+
+        press(&mut app, "2]c");
+        assert_eq!(app.cursor, app.files[0].hunks[1].start);
+        press(&mut app, "9]c");
+        assert_eq!(app.cursor, app.files[1].hunks[0].start);
+        press(&mut app, "5[f");
+        assert_eq!(app.cursor, app.files[0].start);
+    }
+
+    #[test]
+    fn counts_are_clamped() {
+        let mut parser = KeyParser::default();
+        let command = feed(&mut parser, &format!("{}j", "9".repeat(40))).unwrap();
+        assert_eq!(command.count, Some(MAX_COUNT));
+    }
+}
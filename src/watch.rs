@@ -0,0 +1,88 @@
+use crate::vcs::VcsType;
+use anyhow::{Context, Result};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use std::path::{Component, Path};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// Quiet period before a burst of file changes triggers a reload
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches the working tree and re-runs the diff when files change
+pub struct DiffWatcher {
+    _debouncer: Debouncer<RecommendedWatcher>, // Stops watching when dropped
+    diffs: Receiver<Result<String>>,
+}
+
+impl DiffWatcher {
+    /// Start watching `root`; `diff_text` is the diff already on screen
+    pub fn new(
+        root: &Path,
+        vcs: VcsType,
+        vcs_ref: Option<String>,
+        diff_text: String,
+    ) -> Result<Self> {
+        let (tx, diffs) = mpsc::channel();
+        let root_path = root.to_path_buf();
+        let mut last = diff_text;
+
+        // The diff is taken on the watcher's thread so the TUI never waits
+        // for the VCS
+        let mut debouncer = new_debouncer(DEBOUNCE, move |events: DebounceEventResult| {
+            let relevant = match events {
+                Ok(events) => events
+                    .iter()
+                    .any(|e| is_relevant(e.path.strip_prefix(&root_path).unwrap_or(&e.path))),
+                Err(_) => true,
+            };
+            if !relevant {
+                return;
+            }
+
+            match vcs.get_diff(vcs_ref.as_deref()) {
+                // Saving a file without changing it, or touching an ignored
+                // file, leaves the diff as it was
+                Ok(text) if text == last => {}
+                Ok(text) => {
+                    last = text.clone();
+                    let _ = tx.send(Ok(text));
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                }
+            }
+        })
+        .context("Failed to start watching files")?;
+
+        debouncer
+            .watcher()
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+        Ok(Self {
+            _debouncer: debouncer,
+            diffs,
+        })
+    }
+
+    /// The newest diff if it changed since the last call
+    pub fn poll(&self) -> Option<Result<String>> {
+        self.diffs.try_iter().last()
+    }
+}
+
+/// Whether a change to this path (relative to the root) can change the
+/// diff: VCS internals and vr's own session are skipped, except for git
+/// refs moving on a commit or checkout
+fn is_relevant(path: &Path) -> bool {
+    let mut components = path.components().map(|c| match c {
+        Component::Normal(name) => name.to_str().unwrap_or_default(),
+        _ => "",
+    });
+    match components.next() {
+        Some(".git") => matches!(components.next(), Some("HEAD" | "refs")),
+        Some(".hg" | ".vim-review") => false,
+        _ => true,
+    }
+}
@@ -0,0 +1,242 @@
+//! Drives `vr serve --stdio` through JSON-RPC requests in a scratch git
+//! repository, the way an MCP client would
+
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const ROOT_ID: &str = "6f0c8f1e-2b3a-4c5d-8e9f-0a1b2c3d4e5f";
+
+/// A repository with one uncommitted change and one comment on it
+fn repo(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vr-serve-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join(".vim-review")).unwrap();
+
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    };
+    git(&["init", "-q"]);
+    fs::write(dir.join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-qm", "base"]);
+    fs::write(
+        dir.join("lib.rs"),
+        "fn a() {}\nfn b() { todo!() }\nfn c() {}\n",
+    )
+    .unwrap();
+
+    let comments = json!({ "comments": [{
+        "id": ROOT_ID,
+        "file_path": "lib.rs",
+        "line_number": 2,
+        "text": "Don't leave a todo!()",
+        "timestamp": "2024-01-01T00:00:00Z",
+        "context": [{ "content": "+fn b() { todo!() }", "prefix": "" }],
+    }]});
+    fs::write(
+        dir.join(".vim-review/comments.json"),
+        serde_json::to_string_pretty(&comments).unwrap(),
+    )
+    .unwrap();
+    dir
+}
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start(dir: &Path) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_vr"))
+            .args(["serve", "--stdio", "--author", "agent"])
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Self {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        }
+    }
+
+    /// Send one line and read the response line
+    fn send(&mut self, message: &str) -> Value {
+        writeln!(self.stdin, "{}", message).unwrap();
+        self.stdin.flush().unwrap();
+        let mut line = String::new();
+        self.stdout.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let request =
+            json!({ "jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params });
+        let response = self.send(&request.to_string());
+        assert_eq!(response["id"], self.next_id);
+        response
+    }
+
+    /// A tool's result, called as a plain JSON-RPC method
+    fn call(&mut self, tool: &str, args: Value) -> Value {
+        let response = self.request(tool, args);
+        assert!(response.get("error").is_none(), "{}", response);
+        response["result"].clone()
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn stored_comments(dir: &Path) -> Vec<Value> {
+    let content = fs::read_to_string(dir.join(".vim-review/comments.json")).unwrap();
+    serde_json::from_str::<Value>(&content).unwrap()["comments"]
+        .as_array()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn mcp_handshake_and_tool_call() {
+    let dir = repo("mcp");
+    let mut client = Client::start(&dir);
+
+    let init = client.request(
+        "initialize",
+        json!({ "protocolVersion": "2025-03-26", "clientInfo": { "name": "harness" } }),
+    );
+    assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(init["result"]["serverInfo"]["name"], "vr");
+
+    let tools = client.request("tools/list", json!({}));
+    let names: Vec<&str> = tools["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "list_comments",
+            "get_diff",
+            "get_context",
+            "resolve_comment",
+            "reply"
+        ]
+    );
+
+    let listed = client.request(
+        "tools/call",
+        json!({ "name": "list_comments", "arguments": { "status": "open" } }),
+    );
+    assert_eq!(listed["result"]["isError"], false);
+    let text = listed["result"]["content"][0]["text"].as_str().unwrap();
+    assert!(text.contains("Don't leave a todo!()"), "{}", text);
+
+    // Tool failures are results the model can read, not protocol errors
+    let failed = client.request(
+        "tools/call",
+        json!({ "name": "reply", "arguments": { "id": "nope", "text": "x" } }),
+    );
+    assert_eq!(failed["result"]["isError"], true);
+}
+
+#[test]
+fn reply_and_resolve_write_the_session() {
+    let dir = repo("write");
+    let mut client = Client::start(&dir);
+
+    // Ids can be shortened to a unique prefix
+    let reply = client.call("reply", json!({ "id": &ROOT_ID[..8], "text": "Fixed" }));
+    assert_eq!(reply["in_reply_to"], ROOT_ID);
+    assert_eq!(reply["author"], "agent");
+
+    let resolved = client.call("resolve_comment", json!({ "id": ROOT_ID }));
+    assert_eq!(resolved["status"], "resolved");
+
+    let stored = stored_comments(&dir);
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0]["status"], "resolved");
+    assert_eq!(stored[1]["text"], "Fixed");
+
+    let open = client.call("list_comments", json!({ "status": "open" }));
+    assert_eq!(open, json!([]));
+}
+
+#[test]
+fn diff_and_context() {
+    let dir = repo("context");
+    let mut client = Client::start(&dir);
+
+    let diff = client.call("get_diff", json!({ "file": "lib.rs" }));
+    assert!(diff.as_str().unwrap().contains("+fn b() { todo!() }"));
+
+    let context = client.call("get_context", json!({ "id": ROOT_ID, "lines": 1 }));
+    assert_eq!(
+        context,
+        "lib.rs:2\n  1 | fn a() {}\n> 2 | fn b() { todo!() }\n  3 | fn c() {}\n"
+    );
+
+    // A huge line count is clamped rather than overflowing
+    let all = client.call(
+        "get_context",
+        json!({ "file": "lib.rs", "line": 2, "lines": u64::MAX }),
+    );
+    assert!(all.as_str().unwrap().contains("fn c() {}"));
+
+    for file in ["/etc/hostname", "../outside", "lib.rs/../../outside"] {
+        let response = client.request("get_context", json!({ "file": file, "line": 1 }));
+        assert!(
+            response["error"]["message"].is_string(),
+            "{}: {}",
+            file,
+            response
+        );
+    }
+}
+
+#[test]
+fn protocol_errors_and_batches() {
+    let dir = repo("protocol");
+    let mut client = Client::start(&dir);
+
+    assert_eq!(client.send("{not json")["error"]["code"], -32700);
+    assert_eq!(client.request("nope", json!({}))["error"]["code"], -32601);
+    assert_eq!(
+        client.request("tools/call", json!({ "name": "nope" }))["error"]["code"],
+        -32602
+    );
+
+    // Notifications get no response, so the batch answers only the ping
+    let batch = client.send(
+        &json!([
+            { "jsonrpc": "2.0", "method": "notifications/initialized" },
+            { "jsonrpc": "2.0", "id": 7, "method": "ping" },
+        ])
+        .to_string(),
+    );
+    assert_eq!(batch, json!([{ "jsonrpc": "2.0", "id": 7, "result": {} }]));
+}